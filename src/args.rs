@@ -1,70 +1,743 @@
-use clap::{ArgAction, Parser};
-use serde::Serialize;
+use crate::tile_filter::TileFilter;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use gdal_sys::GDALResampleAlg;
+use image::imageops::FilterType;
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use tilemath::Tile;
 
-#[derive(clap::ValueEnum, Clone, Default, Debug, Serialize, Copy)]
+#[derive(clap::ValueEnum, Clone, Default, Debug, Serialize, Deserialize, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum Format {
     #[default]
     JPEG,
+    /// 32-bit RGBA (or 16-bit gray + alpha) PNG
+    #[value(alias = "png32")]
+    #[serde(alias = "png32")]
     PNG,
+    /// 8-bit palette PNG with the colors quantized by NeuQuant
+    PNG8,
+    /// Lossy WebP with losslessly compressed alpha
+    #[value(name = "webp")]
+    WebP,
+    /// AVIF, slow to encode; can't be used with `--continue-file`
+    AVIF,
+    /// Elevation of single band source encoded to RGB PNG as Terrain-RGB
+    #[value(name = "terrain-rgb")]
+    #[serde(rename = "terrain-rgb")]
+    TerrainRgb,
+    /// Elevation of single band source encoded to RGB PNG as Terrarium
+    Terrarium,
+    /// JPEG for dense (mostly opaque) tiles, PNG for sparse tiles
+    Auto,
+}
+
+impl Format {
+    /// Returns file extension of the tiles, `None` for `Auto` mixing JPEG and PNG.
+    pub const fn extension(self) -> Option<&'static str> {
+        match self {
+            Self::JPEG => Some("jpg"),
+            Self::PNG | Self::PNG8 | Self::TerrainRgb | Self::Terrarium => Some("png"),
+            Self::WebP => Some("webp"),
+            Self::AVIF => Some("avif"),
+            Self::Auto => None,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Single *.mbtiles file
+    #[default]
+    Mbtiles,
+    /// Directory of `{zoom}/{x}/{y}.{ext}` tile files with TileJSON in `metadata.json`
+    Directory,
+    /// Single *.pmtiles archive converted from a temporary MBTiles file on completion
+    Pmtiles,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Deserialize, Copy)]
+pub enum JpegSubsampling {
+    #[value(name = "4:4:4")]
+    #[serde(rename = "4:4:4")]
+    R444,
+    #[value(name = "4:2:2")]
+    #[serde(rename = "4:2:2")]
+    R422,
+    #[value(name = "4:2:0")]
+    #[serde(rename = "4:2:0")]
+    R420,
+}
+
+impl From<JpegSubsampling> for jpeg_encoder::SamplingFactor {
+    fn from(value: JpegSubsampling) -> Self {
+        match value {
+            JpegSubsampling::R444 => Self::R_4_4_4,
+            JpegSubsampling::R422 => Self::R_4_2_2,
+            JpegSubsampling::R420 => Self::R_4_2_0,
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum TileRowOrder {
+    /// Row 0 at the bottom as per MBTiles specification
+    #[default]
+    Tms,
+    /// Row 0 at the top
+    Xyz,
+}
+
+impl TileRowOrder {
+    /// Returns `tile_row` of the tile stored in MBTiles.
+    pub fn row(self, tile: &Tile) -> u32 {
+        match self {
+            Self::Tms => tile.reversed_y(),
+            Self::Xyz => tile.y,
+        }
+    }
+
+    /// Returns the tile of the stored `tile_row`.
+    pub fn tile(self, zoom: u8, x: u32, row: u32) -> Tile {
+        let tile = Tile { zoom, x, y: row };
+
+        match self {
+            Self::Tms => Tile {
+                y: tile.reversed_y(),
+                ..tile
+            },
+            Self::Xyz => tile,
+        }
+    }
+
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Tms => "tms",
+            Self::Xyz => "xyz",
+        }
+    }
+}
+
+/// Storage of the tile alpha. There is deliberately no mode sharing a single global mask by all
+/// the tiles, the mask would be as large as the coverage rasterized at the highest zoom and no
+/// tile server could read it.
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum AlphaStorage {
+    /// Store alpha of every tile (in `tile_alpha` column for JPEG)
+    #[default]
+    PerTile,
+    /// Don't store alpha, treat all tiles as opaque
+    None,
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum SourcePriority {
+    /// Use the earliest source covering the pixel
+    FirstWins,
+    /// Use the latest source covering the pixel
+    #[default]
+    LastWins,
+    /// Use the source with the highest alpha at each pixel
+    MaxAlpha,
+    /// Weighted average of the sources by their alpha and distance from their edge
+    Blend,
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum TaskQueueStrategy {
+    /// Process the most recently queued task first, keeping spatially related tiles together
+    #[default]
+    Lifo,
+    /// Process the oldest queued task first, draining the queue evenly
+    Fifo,
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum TileOrdering {
+    /// Morton (Z-order) curve
+    #[default]
+    Zorder,
+    /// Hilbert curve, as the tile IDs of PMTiles, with better spatial locality
+    Hilbert,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum MtimeCheckAction {
+    /// Abort with an error
+    Abort,
+    /// Print a warning and continue
+    Warn,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug, Copy, PartialEq, Eq)]
+pub enum ColorCorrection {
+    /// Sigmoidal contrast by `--color-contrast` around `--color-midpoint`
+    Sigmoid,
+    /// Linear contrast by `--color-contrast` around `--color-midpoint`
+    Linear,
+    /// Gamma correction by `--color-gamma`
+    Gamma,
+}
+
+#[derive(clap::ValueEnum, Clone, Default, Debug, Copy, PartialEq, Eq)]
+pub enum ResamplingAlgorithm {
+    /// Nearest neighbour, for categorical or pixel-art rasters
+    Nearest,
+    Bilinear,
+    Cubic,
+    /// Cubic B-spline
+    CubicSpline,
+    #[default]
+    Lanczos,
+    /// Average of the contributing pixels
+    Average,
+    /// Most frequent value of the contributing pixels
+    Mode,
+}
+
+impl ResamplingAlgorithm {
+    /// Returns GDAL warp resampling algorithm.
+    pub const fn gdal(self) -> GDALResampleAlg::Type {
+        match self {
+            Self::Nearest => GDALResampleAlg::GRA_NearestNeighbour,
+            Self::Bilinear => GDALResampleAlg::GRA_Bilinear,
+            Self::Cubic => GDALResampleAlg::GRA_Cubic,
+            Self::CubicSpline => GDALResampleAlg::GRA_CubicSpline,
+            Self::Lanczos => GDALResampleAlg::GRA_Lanczos,
+            Self::Average => GDALResampleAlg::GRA_Average,
+            Self::Mode => GDALResampleAlg::GRA_Mode,
+        }
+    }
+
+    /// Returns the closest filter for downsampling of the composed children.
+    pub const fn filter_type(self) -> FilterType {
+        match self {
+            // mode must not mix values of categorical rasters
+            Self::Nearest | Self::Mode => FilterType::Nearest,
+            // halving with the triangle filter averages the neighbouring pixels
+            Self::Bilinear | Self::Average => FilterType::Triangle,
+            Self::Cubic => FilterType::CatmullRom,
+            Self::CubicSpline => FilterType::Gaussian,
+            Self::Lanczos => FilterType::Lanczos3,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(
+    version,
+    about,
+    long_about = None,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub args: Option<Args>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Copy readable tiles of a corrupt *.mbtiles file to a new one
+    Recover {
+        /// Corrupt *.mbtiles file
+        file: PathBuf,
+
+        /// Output *.mbtiles file
+        output: PathBuf,
+    },
+    /// List tiles added, removed and changed between two *.mbtiles files generated with --store-checksums
+    DiffTiles {
+        /// Original *.mbtiles file
+        file: PathBuf,
+
+        /// Other *.mbtiles file
+        other: PathBuf,
+    },
+    /// Check GDAL, PROJ and SQLite setup, CPU threads, output directory and optionally the source file
+    HealthCheck {
+        /// Input raster geofile to check
+        #[arg(long)]
+        source_file: Option<PathBuf>,
+
+        /// Directory to check for write access and free space
+        #[arg(long, default_value = ".")]
+        output_dir: PathBuf,
+    },
+}
+
+#[derive(clap::Args, Debug)]
 pub struct Args {
-    /// Input raster geofile
-    #[arg(long)]
-    pub source_file: PathBuf,
+    /// Input raster geofile, also a GDAL virtual file system path like `/vsis3/bucket/dem.tif` or `/vsicurl/https://example.com/ortho.tif`; repeat to combine multiple files into a single VRT
+    #[arg(long, action = ArgAction::Append, required_unless_present_any = ["source_file_list", "source_file_pattern"])]
+    pub source_file: Vec<PathBuf>,
 
-    /// Output *.mbtiles file
+    /// Glob pattern of input raster geofiles to combine into a single VRT, eg. `/data/tiles_*.tif`
+    #[arg(long, conflicts_with_all = ["source_file", "source_file_list"])]
+    pub source_file_pattern: Option<String>,
+
+    /// Match the file name of `--source-file-pattern` also in subdirectories
+    #[arg(long, requires = "source_file_pattern")]
+    pub source_glob_recursive: bool,
+
+    /// File listing input raster geofiles to composite into a single mosaic, one per line as `<path>` or `path=<path> srs=<srs>`. Overlaps are resolved according to `--source-file-priority`.
+    #[arg(long, conflicts_with_all = ["source_file", "continue_file"])]
+    pub source_file_list: Option<PathBuf>,
+
+    /// How to resolve overlaps of the sources of the source file list, pixel by pixel of the highest zoom
+    #[arg(long, default_value_t, value_enum)]
+    pub source_file_priority: SourcePriority,
+
+    /// GDAL configuration option as `<key>=<value>`, eg. `AWS_REGION=eu-central-1` for `/vsis3/` sources; repeatable
+    #[arg(long, action = ArgAction::Append, value_parser = parse_config_option)]
+    pub gdal_config: Vec<(String, String)>,
+
+    /// Output *.mbtiles file or directory
     #[arg(long)]
     pub target_file: PathBuf,
 
+    /// Output format; JPEG tiles are written without alpha to the directory, y of the path follows `--tile-row-order`
+    #[arg(long, default_value_t, value_enum)]
+    pub output_format: OutputFormat,
+
+    /// Write world file (eg. `.pgw` for `.png`) next to every tile of the directory output
+    #[arg(long)]
+    pub write_world_file: bool,
+
+    /// Generate into this file and rename it to `--target-file` on completion
+    #[arg(long)]
+    pub target_file_temp: Option<PathBuf>,
+
+    /// Shorthand for `--target-file-temp <TARGET_FILE>.tmp`
+    #[arg(long, conflicts_with = "target_file_temp")]
+    pub atomic_swap: bool,
+
+    /// Update existing `--target-file` in place, skipping tiles present in it; parents of the generated tiles are composed again
+    #[arg(long, conflicts_with_all = ["target_file_temp", "atomic_swap"])]
+    pub incremental: bool,
+
     /// Continue *.mbtiles file, use same as target-file to continue to the same file.
     #[arg(long)]
     pub continue_file: Option<PathBuf>,
 
+    /// Min zoom level; lower zoom overview tiles are not generated
+    #[arg(long, default_value_t = 0)]
+    pub min_zoom: u8,
+
     /// Max zoom level
     #[arg(long)]
     pub max_zoom: u8,
 
+    /// Decrease max zoom level until the average encoded size of sampled tiles is below this number of bytes
+    #[arg(long)]
+    pub max_zoom_per_tile_size_bytes: Option<usize>,
+
     /// Source SRS
     #[arg(long)]
     pub source_srs: Option<String>,
 
+    /// Source SRS as EPSG code, shorthand for `--source-srs EPSG:<code>`
+    #[arg(long, conflicts_with = "source_srs")]
+    pub source_epsg: Option<u32>,
+
+    /// Skip checking that the source SRS projects the source center into Web Mercator bounds
+    #[arg(long)]
+    pub skip_srs_validation: bool,
+
+    /// Use source mask band (eg. internal mask or `.msk` sidecar) as alpha instead of nodata value
+    #[arg(long, default_value_t = false)]
+    pub source_nodata_mask_band: bool,
+
+    /// Always warp with `GDALReprojectImage`; can't be used with `--transform-pipeline`
+    #[arg(long, conflicts_with_all = ["force_chunk_warp", "transform_pipeline"])]
+    pub force_reproject_image: bool,
+
+    /// Always warp with `GDALChunkAndWarpImage`, also for transformation by SRS
+    #[arg(long)]
+    pub force_chunk_warp: bool,
+
+    /// Maximal error in pixels of the approximate reprojection transformer, 0 for the exact transformer.
+    /// Lower is more accurate but slower, eg. 0.001 for cartographic accuracy or 1.0 for speed
+    #[arg(long, default_value_t = 0.125)]
+    pub reproject_error_threshold: f64,
+
+    /// Resampling algorithm for warping and for downsampling of the composed parent tiles
+    #[arg(long, default_value_t, value_enum)]
+    pub resampling_algorithm: ResamplingAlgorithm,
+
+    /// Resampling algorithm of a zoom level as `<zoom>:<algorithm>`, eg. `12:average`; repeatable. Tiles of `--max-zoom` are warped with the algorithm of the max zoom, tiles of lower zooms are composed from their children downsampled with the algorithm of their zoom
+    #[arg(long, action = ArgAction::Append, value_parser = parse_zoom_resampling_algorithm)]
+    pub resampling_algorithm_per_zoom: Vec<(u8, ResamplingAlgorithm)>,
+
+    /// Memory in MiB the GDAL warper may use per thread, like `gdalwarp -wm`; more reduces chunking of the megatiles but increases RAM use of every thread
+    #[arg(long, default_value_t = 256.0)]
+    pub warp_memory_mb: f64,
+
+    /// Overview level of the sources to warp from, 0 is the full resolution, 1 the first overview and so on
+    #[arg(long, default_value_t = 0)]
+    pub source_overview_level: u8,
+
+    /// Warp from the coarsest overview of every source still at least as detailed as the tiles of the max zoom; the overviews must be built beforehand, eg. with `gdaladdo`
+    #[arg(long, conflicts_with = "source_overview_level")]
+    pub use_overviews: bool,
+
+    /// Warp the max zoom tiles exceeding the resolution of the source at the source resolution and upscale them, by nearest neighbour unless `--resampling-algorithm-per-zoom` sets the algorithm of the max zoom
+    #[arg(long)]
+    pub overzoom: bool,
+
+    /// Palette entry to treat as nodata (transparent) in palette-indexed sources, which are expanded to RGBA
+    #[arg(long)]
+    pub palette_nodata_index: Option<u8>,
+
+    /// Nodata value of all source bands overriding the one of the source, or `none` to ignore it; pixels with all bands of the nodata value are transparent
+    #[arg(long, value_parser = parse_no_data, conflicts_with = "nodata_per_band")]
+    pub nodata: Option<NoData>,
+
+    /// Comma separated nodata values of the source bands overriding the ones of the source
+    #[arg(long, value_delimiter = ',')]
+    pub nodata_per_band: Vec<f64>,
+
+    /// Comma separated per-band scale applied to source values as `value * scale + offset`
+    #[arg(long, value_delimiter = ',')]
+    pub input_scale: Vec<f32>,
+
+    /// Comma separated per-band offset applied to source values as `value * scale + offset`
+    #[arg(long, value_delimiter = ',')]
+    pub input_offset: Vec<f32>,
+
+    /// Color correction of the color channels applied to the warped megatiles
+    #[arg(long, value_enum)]
+    pub color_correct: Option<ColorCorrection>,
+
+    /// Contrast of the sigmoid and linear color correction
+    #[arg(long, default_value_t = 5.0)]
+    pub color_contrast: f64,
+
+    /// Midpoint (0..1) of the sigmoid and linear color correction
+    #[arg(long, default_value_t = 0.5)]
+    pub color_midpoint: f64,
+
+    /// Exponent of the gamma color correction
+    #[arg(long, default_value_t = 1.0)]
+    pub color_gamma: f64,
+
     /// Projection transformation pipeline
     #[arg(long)]
     pub transform_pipeline: Option<String>,
 
-    /// Bounding polygon in `GeoJSON` file
+    /// Bounding polygons (including parts of multipolygons) in `GeoJSON` file; tiles intersecting any of them are generated
     #[arg(long)]
     pub bounding_polygon: Option<PathBuf>,
 
+    /// Clip the warped sources to polygons (including parts of multipolygons) in `GeoJSON` file as GDAL cutline; pixels outside of them are transparent
+    #[arg(long)]
+    pub cutline: Option<PathBuf>,
+
+    /// Bounding box in EPSG:4326 as `min_lon,min_lat,max_lon,max_lat`; with `--bounding-polygon` tiles must be within both
+    #[arg(
+        long,
+        value_delimiter = ',',
+        num_args = 4,
+        allow_negative_numbers = true
+    )]
+    pub bbox: Option<Vec<f64>>,
+
+    /// Skip tiles intersecting any polygon in `GeoJSON` file
+    #[arg(long)]
+    pub skip_tiles_geojson: Option<PathBuf>,
+
+    /// Generate only tiles matching the expression, eg. `zoom >= 14 AND (x % 2 == 0 OR y % 3 == 0)`; supports `zoom`, `x`, `y`, `reversed_y`, comparisons, `AND`, `OR`, `NOT`, `%`, `/`, `*`, `+`, `-` and parentheses
+    #[arg(long, value_parser = TileFilter::from_expr)]
+    pub tile_filter_expr: Option<TileFilter>,
+
+    /// Crop the source to the area of the covered tiles into a temporary GeoTIFF before warping
+    #[arg(long)]
+    pub pre_clip_source: bool,
+
+    /// Directory to persist warped megatiles to and reuse them in subsequent runs with unchanged sources
+    #[arg(long)]
+    pub megatile_cache: Option<PathBuf>,
+
+    /// Like `--megatile-cache` but for directory shared by multiple processes or machines (eg. on NFS),
+    /// megatiles are warped under file lock so that the same megatile is not warped more times
+    #[arg(long, conflicts_with = "megatile_cache")]
+    pub output_metatile_dir: Option<PathBuf>,
+
+    /// Save the megatile as `<target>_megatile_z{Z}_x{X}_y{Y}.png` before slicing, specified as `zoom/x/y`
+    #[arg(long)]
+    pub debug_megatile: Option<Tile>,
+
+    /// Also save alpha of `--debug-megatile` as a separate grayscale PNG
+    #[arg(long, requires = "debug_megatile")]
+    pub debug_megatile_alpha: bool,
+
+    /// Keep temporary files (like the pre-clipped source) after processing
+    #[arg(long)]
+    pub keep_temp_files: bool,
+
+    /// Check periodically if modification time of the sources has changed during the run
+    #[arg(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "abort")]
+    pub source_file_mtime_check: Option<MtimeCheckAction>,
+
+    /// Number of warped megatiles between the checks of `--source-file-mtime-check`
+    #[arg(long, default_value_t = 1000, value_parser = clap::value_parser!(u32).range(1..))]
+    pub mtime_check_interval: u32,
+
     /// Tile size
     #[arg(long, default_value_t = 256)]
     pub tile_size: u16,
 
+    /// Generate tiles of double the tile size stored one zoom level lower (for `@2x` displays)
+    #[arg(long)]
+    pub retina: bool,
+
+    /// Pixels of the neighbouring tiles included on every side of the tile, stored tiles are of the tile size plus twice the padding
+    #[arg(long, default_value_t = 0)]
+    pub tile_padding: u16,
+
+    /// Row order of `tile_row` in the tiles table
+    #[arg(long, default_value_t, value_enum)]
+    pub tile_row_order: TileRowOrder,
+
     /// Number of threads for parallel processing [default: available parallelism]
     #[arg(long)]
     pub num_threads: Option<u16>,
 
+    /// Order in which every thread takes tasks from its queue
+    #[arg(long, default_value_t, value_enum)]
+    pub task_queue_strategy: TaskQueueStrategy,
+
+    /// Space-filling curve the max zoom tiles are processed along
+    #[arg(long, default_value_t, value_enum)]
+    pub tile_ordering: TileOrdering,
+
+    /// Number of connections to `--continue-file` for reading already generated tiles [default: half of the threads]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    pub resume_db_connections: Option<u16>,
+
     #[arg(long, default_value_t, value_enum)]
     pub format: Format,
 
+    /// Minimal fraction of opaque pixels of a tile to encode it as JPEG with `--format auto`
+    #[arg(long, default_value_t = 0.8)]
+    pub auto_format_threshold: f32,
+
+    /// How to store alpha channel of tiles
+    #[arg(long, default_value_t, value_enum)]
+    pub tile_alpha_storage: AlphaStorage,
+
+    /// Additionally compress PNG tiles with zstd using a dictionary trained on the first tiles
+    #[arg(long, default_value_t = false)]
+    pub png_post_compress: bool,
+
+    /// Named preset of quality related options: `web`, `print`, `preview`, `archive` or a custom one from `$XDG_CONFIG_HOME/freemap-tiler/profiles.toml`. Explicitly specified options take precedence.
+    #[arg(long)]
+    pub format_profile: Option<String>,
+
     /// JPEG quality
     #[arg(long, default_value_t = 85)]
     pub jpeg_quality: u8,
 
+    /// WebP quality
+    #[arg(long, default_value_t = 80)]
+    pub webp_quality: u8,
+
+    /// AVIF quality
+    #[arg(long, default_value_t = 80)]
+    pub avif_quality: u8,
+
+    /// AVIF encoding speed from 1 (slowest, smallest files) to 10 (fastest)
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..=10))]
+    pub avif_speed: u8,
+
+    /// Elevation in meters of the Terrain-RGB value 0 with `--format terrain-rgb`
+    #[arg(long, default_value_t = -10000.0, allow_negative_numbers = true)]
+    pub terrain_base_offset: f64,
+
+    /// Elevation step in meters of the Terrain-RGB value with `--format terrain-rgb`
+    #[arg(long, default_value_t = 0.1)]
+    pub terrain_scale: f64,
+
+    /// JPEG chroma subsampling [default: 4:2:0 for quality below 90, otherwise 4:4:4]
+    #[arg(long, value_enum)]
+    pub jpeg_subsampling: Option<JpegSubsampling>,
+
+    /// Zstd compression level of the alpha channel
+    #[arg(long, default_value_t = 0)]
+    pub alpha_compression_level: i32,
+
+    /// Number of MCUs between JPEG restart markers, 0 for none. Markers add about 0.5 % to the tile size but allow partial recovery of corrupted tiles and parallel decoding.
+    #[arg(long, default_value_t = 0)]
+    pub jpeg_restart_interval: u16,
+
     /// Advanced: zoom offset of a parent tile to reproject at once. Modify to fine-tune the performance.
     #[arg(long, default_value_t = 3)]
     pub warp_zoom_offset: u8,
 
-    /// Debug
+    /// Advanced: number of horizontal strips to read the warped megatile in to lower the memory usage
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub warp_read_strips: u16,
+
+    /// Advanced: benchmark warping with zoom offsets 1 to 5 and use the fastest one instead of --warp-zoom-offset.
+    #[arg(long, default_value_t = false)]
+    pub auto_tune_warp_offset: bool,
+
+    /// Value of `name` metadata
+    #[arg(long, default_value = "Tiles")]
+    pub output_name: String,
+
+    /// Value of `description` metadata
+    #[arg(long)]
+    pub output_description: Option<String>,
+
+    /// Value of `attribution` metadata, eg. `© OpenStreetMap contributors`
+    #[arg(long)]
+    pub output_attribution: Option<String>,
+
+    /// Additional metadata as JSON object of string values, eg. `{"attribution":"..."}`
+    #[arg(long)]
+    pub metadata_json: Option<String>,
+
+    /// Additional metadata entry as `key=value`, can be repeated; takes precedence over --metadata-json
+    #[arg(long, action = ArgAction::Append)]
+    pub metadata: Vec<String>,
+
+    /// Write WKT2 of the tiles CRS to `crs_wkt` metadata
+    #[arg(long, default_value_t = false)]
+    pub tile_crs_wkt: bool,
+
+    /// Hours after which the tiles expire; writes `tile_expiry_hours` and `generated_at` (Unix timestamp) metadata
+    #[arg(long)]
+    pub tile_expiry_hours: Option<u32>,
+
+    /// Allow --metadata-json and --metadata to override reserved metadata keys
+    #[arg(long, default_value_t = false)]
+    pub allow_metadata_override: bool,
+
+    /// Read all blocks of the sources and check their bands and geo-transform before tiling
+    #[arg(long, default_value_t = false)]
+    pub validate_input: bool,
+
+    /// Print statistics of source bands and exit
+    #[arg(long, default_value_t = false)]
+    pub print_stats: bool,
+
+    /// Print number of tiles per zoom level and the estimated output size and exit without writing the target
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Budget of the cache of tiles waiting to compose their parent, in MiB; only tiles no longer needed are evicted
+    #[arg(long, default_value_t = 512)]
+    pub cache_size_mb: usize,
+
+    /// Number of tiles inserted in a single transaction
+    #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u16).range(1..))]
+    pub batch_size: u16,
+
+    /// Store every distinct tile image once, in `image_data` table keyed by its SHA-256 referenced from `map` table; `tiles` is a view
+    #[arg(long, default_value_t = false)]
+    pub dedup: bool,
+
+    /// Store SHA-256 checksums of tiles to verify them when continuing and to compare outputs by the `diff-tiles` command
+    #[arg(long, default_value_t = false)]
+    pub store_checksums: bool,
+
+    /// Write `zoom/x/y` of every generated non-empty tile as a line to the file
+    #[arg(long)]
+    pub emit_tile_list: Option<PathBuf>,
+
+    /// Write GeoJSON of the polygons of the max zoom tiles to generate to the file before tiling, also with `--dry-run`
+    #[arg(long)]
+    pub coverage_geojson: Option<PathBuf>,
+
+    /// Write TileJSON 3.0.0 describing the tiles to the file, with tile URLs `{z}/{x}/{y}.<ext>` relative to the file
+    #[arg(long)]
+    pub tilejson: Option<PathBuf>,
+
+    /// ZSTD compressed JSON file the processed tiles are periodically written to; with `--continue-file` same as `--target-file` the tiles processed by the crashed run are skipped
+    #[arg(long, visible_alias = "state-file")]
+    pub progress_file: Option<PathBuf>,
+
+    /// Seconds between writes of `--progress-file`
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    pub progress_interval: u32,
+
+    /// Number of tiles between flushes of `--emit-tile-list`
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub emit_tile_list_flush_interval: u32,
+
+    /// Show an in-place updated progress bar instead of periodic stats lines (if stdout is a terminal)
+    #[arg(long)]
+    pub progress_bar: bool,
+
+    /// Print the periodic stats and the final summary as newline-delimited JSON objects instead of human-readable lines
+    #[arg(long, conflicts_with = "progress_bar")]
+    pub json_progress: bool,
+
+    /// Print a warning if the lock contention of a shared structure exceeds the percentage
+    #[arg(long)]
+    pub contention_threshold_pct: Option<f32>,
+
+    /// Log at debug level, including processing steps of every tile; `RUST_LOG` takes precedence
     #[arg(long, default_value_t = false)]
     pub debug: bool,
 
+    /// Comma separated zoom levels to not store; tiles are still rendered to compose lower zooms
+    #[arg(long, value_delimiter = ',')]
+    pub skip_zoom: Vec<u8>,
+
+    /// Width in pixels of the zone blended across the seams of the child tiles composed into a lower zoom tile
+    #[arg(long, default_value_t = 0)]
+    pub tile_blend_edges: u16,
+
     /// Insert empty
     #[arg(long, action = ArgAction::Set, default_value_t = true, default_missing_value = "true", num_args = 0..=1, require_equals = false)]
     pub insert_empty: bool,
+
+    /// Minimal alpha of a pixel to not be treated as transparent; tiles without such pixels are empty
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
+    pub alpha_threshold: u8,
+}
+
+/// Nodata value of `--nodata`
+#[derive(Clone, Copy, Debug)]
+pub enum NoData {
+    /// Source bands have no nodata value
+    None,
+    Value(f64),
+}
+
+/// Parses `none` or number of `--nodata`.
+fn parse_no_data(s: &str) -> Result<NoData, String> {
+    if s == "none" {
+        return Ok(NoData::None);
+    }
+
+    s.parse()
+        .map(NoData::Value)
+        .map_err(|_| format!("expected number or none, got {s}"))
+}
+
+/// Parses `<zoom>:<algorithm>` of `--resampling-algorithm-per-zoom`.
+fn parse_zoom_resampling_algorithm(s: &str) -> Result<(u8, ResamplingAlgorithm), String> {
+    let (zoom, algorithm) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected <zoom>:<algorithm>, got {s}"))?;
+
+    Ok((
+        zoom.parse()
+            .map_err(|_| format!("invalid zoom level {zoom}"))?,
+        ResamplingAlgorithm::from_str(algorithm, true)?,
+    ))
+}
+
+/// Parses `<key>=<value>` of `--gdal-config`.
+fn parse_config_option(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected <key>=<value>, got {s}")),
+    }
 }
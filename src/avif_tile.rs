@@ -0,0 +1,54 @@
+use ravif::{Encoder, Img, RGB8, RGBA8};
+
+/// Encodes the interleaved G+A or RGB+A pixels as AVIF. Without `with_alpha` the alpha is dropped.
+///
+/// `speed` is 1 (slowest, smallest) to 10 (fastest).
+pub fn encode(
+    data: &[u8],
+    band_count: usize,
+    size: u16,
+    quality: u8,
+    speed: u8,
+    with_alpha: bool,
+) -> Result<Vec<u8>, ravif::Error> {
+    let size = usize::from(size);
+
+    let encoder = Encoder::new()
+        .with_quality(f32::from(quality))
+        .with_alpha_quality(f32::from(quality))
+        .with_speed(speed);
+
+    let rgb = |chunk: &[u8]| {
+        if band_count == 2 {
+            (chunk[0], chunk[0], chunk[0])
+        } else {
+            (chunk[0], chunk[1], chunk[2])
+        }
+    };
+
+    let encoded = if with_alpha {
+        let pixels: Vec<_> = data
+            .chunks_exact(band_count)
+            .map(|chunk| {
+                let (r, g, b) = rgb(chunk);
+
+                RGBA8::new(r, g, b, chunk[band_count - 1])
+            })
+            .collect();
+
+        encoder.encode_rgba(Img::new(pixels.as_slice(), size, size))?
+    } else {
+        let pixels: Vec<_> = data
+            .chunks_exact(band_count)
+            .map(|chunk| {
+                let (r, g, b) = rgb(chunk);
+
+                RGB8::new(r, g, b)
+            })
+            .collect();
+
+        encoder.encode_rgb(Img::new(pixels.as_slice(), size, size))?
+    };
+
+    Ok(encoded.avif_file)
+}
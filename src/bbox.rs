@@ -0,0 +1,166 @@
+use geo::{Intersects, LineString, Polygon};
+use rayon::{
+    iter::{IntoParallelIterator, MapWith, ParallelIterator},
+    range,
+};
+use tilemath::{BBox, Tile, WEB_MERCATOR_EXTENT};
+
+/// Rectangular range of tiles of a single zoom level
+#[derive(Clone, Debug)]
+pub struct TileRange {
+    pub zoom: u8,
+    pub min_x: u32,
+    pub max_x: u32,
+    pub min_y: u32,
+    pub max_y: u32,
+}
+
+impl TileRange {
+    /// Returns range of tiles covering specified web mercator bounding box at specified zoom.
+    pub fn from_bbox(bbox: &BBox, zoom: u8) -> Self {
+        let tile_size_meters = (WEB_MERCATOR_EXTENT * 2.0) / f64::from(1 << zoom);
+
+        Self {
+            zoom,
+            min_x: ((bbox.min_x + WEB_MERCATOR_EXTENT) / tile_size_meters).floor() as u32,
+            max_x: ((bbox.max_x + WEB_MERCATOR_EXTENT) / tile_size_meters).ceil() as u32 - 1,
+            min_y: ((WEB_MERCATOR_EXTENT - bbox.max_y) / tile_size_meters).floor() as u32,
+            max_y: ((WEB_MERCATOR_EXTENT - bbox.min_y) / tile_size_meters).ceil() as u32 - 1,
+        }
+    }
+
+    const fn width(&self) -> usize {
+        if self.max_x < self.min_x {
+            0
+        } else {
+            (self.max_x - self.min_x) as usize + 1
+        }
+    }
+
+    const fn height(&self) -> usize {
+        if self.max_y < self.min_y {
+            0
+        } else {
+            (self.max_y - self.min_y) as usize + 1
+        }
+    }
+
+    /// Returns up to `count` tiles spread over the range. The range is divided into a grid of
+    /// strata of about the same shape and a tile is picked from every stratum by LCG seeded by `seed`.
+    pub fn sample(&self, count: usize, seed: u64) -> Vec<Tile> {
+        let width = self.width();
+
+        let height = self.height();
+
+        if count >= width * height {
+            return (0..width * height).map(|i| self.tile_at(i)).collect();
+        }
+
+        if count == 0 {
+            return vec![];
+        }
+
+        let cols =
+            ((count as f64 * width as f64 / height as f64).sqrt().ceil() as usize).clamp(1, width);
+
+        let rows = count.div_ceil(cols).clamp(1, height);
+
+        let mut state = seed;
+
+        let mut random = |n: usize| {
+            state = state
+                .wrapping_mul(6_364_136_223_846_793_005)
+                .wrapping_add(1_442_695_040_888_963_407);
+
+            ((state >> 33) % n as u64) as usize
+        };
+
+        let mut strata = Vec::with_capacity(rows * cols);
+
+        for row in 0..rows {
+            let min_y = row * height / rows;
+
+            let max_y = (row + 1) * height / rows;
+
+            for col in 0..cols {
+                let min_x = col * width / cols;
+
+                let max_x = (col + 1) * width / cols;
+
+                strata.push(Tile {
+                    zoom: self.zoom,
+                    x: self.min_x + (min_x + random(max_x - min_x)) as u32,
+                    y: self.min_y + (min_y + random(max_y - min_y)) as u32,
+                });
+            }
+        }
+
+        // evenly drop the surplus strata
+        (0..count)
+            .map(|i| strata[i * strata.len() / count])
+            .collect()
+    }
+
+    /// Returns tile of the specified index, counting by rows.
+    const fn tile_at(&self, index: usize) -> Tile {
+        let width = self.width();
+
+        Tile {
+            zoom: self.zoom,
+            x: self.min_x + (index % width) as u32,
+            y: self.min_y + (index / width) as u32,
+        }
+    }
+}
+
+impl IntoParallelIterator for TileRange {
+    type Iter = MapWith<range::Iter<usize>, Self, fn(&mut Self, usize) -> Tile>;
+
+    type Item = Tile;
+
+    fn into_par_iter(self) -> Self::Iter {
+        let count = self.width() * self.height();
+
+        (0..count)
+            .into_par_iter()
+            .map_with(self, |range, index| range.tile_at(index))
+    }
+}
+
+/// Parallel version of `tilemath::bbox_covered_tiles` which splits the range without iterating it.
+pub fn covered_tiles_par(bbox: &BBox, zoom: u8) -> impl ParallelIterator<Item = Tile> {
+    TileRange::from_bbox(bbox, zoom).into_par_iter()
+}
+
+/// Returns polygon of the tile bounds in web mercator.
+pub fn tile_polygon(tile: &Tile, tile_size: u16) -> Polygon {
+    let bounds = tile.bounds(tile_size);
+
+    Polygon::new(
+        LineString::from(vec![
+            (bounds.min_x, bounds.min_y),
+            (bounds.max_x, bounds.min_y),
+            (bounds.max_x, bounds.max_y),
+            (bounds.min_x, bounds.max_y),
+            (bounds.min_x, bounds.min_y),
+        ]),
+        vec![],
+    )
+}
+
+/// Like `covered_tiles_par` but returns only the tiles intersecting any of the polygons. The tiles
+/// are filtered as they are generated, so the whole bounding box coverage is never held in memory.
+pub fn covered_tiles_within_polygons(
+    bbox: &BBox,
+    zoom: u8,
+    tile_size: u16,
+    polygons: &[Polygon],
+) -> impl ParallelIterator<Item = Tile> {
+    covered_tiles_par(bbox, zoom).filter(move |tile| {
+        let tile_polygon = tile_polygon(tile, tile_size);
+
+        polygons
+            .iter()
+            .any(|polygon| tile_polygon.intersects(polygon))
+    })
+}
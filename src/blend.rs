@@ -0,0 +1,47 @@
+/// Blends pixels within `width` pixels of the seams between the four children composed in the
+/// square buffer of `size` pixels, the seams being before the column and row `seam`. The pixel
+/// at the seam is mixed half and half with its mirror across the seam, linearly transitioning
+/// to the unchanged pixel at the `width` distance.
+/// Colors are weighted by alpha so that transparent pixels don't darken the opaque ones.
+pub fn blend_edges(buffer: &mut [u8], size: usize, seam: usize, band_count: usize, width: usize) {
+    let width = width.min(seam).min(size - seam);
+
+    for vertical in [true, false] {
+        let source = buffer.to_vec();
+
+        for i in 0..size {
+            for d in 0..width {
+                let weight = 0.5 + 0.5 * (d as f32 + 0.5) / width as f32;
+
+                let (near, far) = if vertical {
+                    (i * size + seam - 1 - d, i * size + seam + d)
+                } else {
+                    ((seam - 1 - d) * size + i, (seam + d) * size + i)
+                };
+
+                for (own, other) in [(near, far), (far, near)] {
+                    let own = own * band_count;
+
+                    let other = other * band_count;
+
+                    let own_alpha = weight * f32::from(source[own + band_count - 1]);
+
+                    let other_alpha = (1.0 - weight) * f32::from(source[other + band_count - 1]);
+
+                    let alpha = own_alpha + other_alpha;
+
+                    if alpha > 0.0 {
+                        for b in 0..band_count - 1 {
+                            buffer[own + b] = ((own_alpha * f32::from(source[own + b])
+                                + other_alpha * f32::from(source[other + b]))
+                                / alpha)
+                                .round() as u8;
+                        }
+                    }
+
+                    buffer[own + band_count - 1] = alpha.round() as u8;
+                }
+            }
+        }
+    }
+}
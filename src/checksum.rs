@@ -0,0 +1,19 @@
+use sha2::{Digest, Sha256};
+
+/// Returns lowercase hex encoded SHA-256 of the data
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex(&Sha256::digest(data))
+}
+
+/// Returns lowercase hex encoded SHA-256 of the stored tile data followed by its stored alpha,
+/// equal to `sha256_hex` of the data for the tiles without alpha
+pub fn tile_checksum(data: &[u8], alpha: &[u8]) -> String {
+    hex(&Sha256::new()
+        .chain_update(data)
+        .chain_update(alpha)
+        .finalize())
+}
+
+fn hex(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{b:02x}")).collect()
+}
@@ -0,0 +1,77 @@
+use gdal::{Dataset, errors::GdalError};
+use gdal_sys::{GDALClose, GDALTranslate, GDALTranslateOptionsFree, GDALTranslateOptionsNew};
+use std::{
+    ffi::{CString, c_char},
+    path::Path,
+    ptr,
+};
+use tilemath::BBox;
+
+/// Crops the source dataset to the bounding box (in source CRS) like `gdal_translate -projwin`
+/// and saves the result as a LZW compressed tiled GeoTIFF.
+pub fn clip_to_geotiff(
+    source_ds: &Dataset,
+    bbox: &BBox,
+    target_file: &Path,
+) -> Result<(), GdalError> {
+    let args = [
+        "-projwin".to_string(),
+        bbox.min_x.to_string(),
+        bbox.max_y.to_string(),
+        bbox.max_x.to_string(),
+        bbox.min_y.to_string(),
+        "-of".to_string(),
+        "GTiff".to_string(),
+        "-co".to_string(),
+        "COMPRESS=LZW".to_string(),
+        "-co".to_string(),
+        "TILED=YES".to_string(),
+        "-co".to_string(),
+        "BIGTIFF=IF_SAFER".to_string(),
+    ];
+
+    let args: Vec<_> = args
+        .into_iter()
+        .map(|arg| CString::new(arg).expect("argument should not contain NUL"))
+        .collect();
+
+    let mut argv: Vec<*mut c_char> = args.iter().map(|arg| arg.as_ptr().cast_mut()).collect();
+
+    argv.push(ptr::null_mut());
+
+    let target_file = CString::new(target_file.to_string_lossy().as_bytes())
+        .expect("path should not contain NUL");
+
+    unsafe {
+        let options = GDALTranslateOptionsNew(argv.as_mut_ptr(), ptr::null_mut());
+
+        if options.is_null() {
+            return Err(GdalError::NullPointer {
+                method_name: "GDALTranslateOptionsNew",
+                msg: String::new(),
+            });
+        }
+
+        let mut usage_error = 0;
+
+        let result = GDALTranslate(
+            target_file.as_ptr(),
+            source_ds.c_dataset(),
+            options,
+            &raw mut usage_error,
+        );
+
+        GDALTranslateOptionsFree(options);
+
+        if result.is_null() {
+            return Err(GdalError::NullPointer {
+                method_name: "GDALTranslate",
+                msg: format!("usage error: {usage_error}"),
+            });
+        }
+
+        GDALClose(result);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::args::ColorCorrection;
+
+/// Returns lookup table mapping input channel values to the color corrected ones.
+///
+/// Sigmoid applies `1 / (1 + exp(-contrast * (input - midpoint)))`, linear stretches values
+/// around the midpoint by the contrast and gamma applies `input ^ gamma`, all on values scaled to 0..1.
+pub fn lookup_table(
+    correction: ColorCorrection,
+    contrast: f64,
+    midpoint: f64,
+    gamma: f64,
+) -> [u8; 256] {
+    let mut table = [0u8; 256];
+
+    for (input, output) in table.iter_mut().enumerate() {
+        let value = input as f64 / 255.0;
+
+        let value = match correction {
+            ColorCorrection::Sigmoid => 1.0 / (1.0 + (-contrast * (value - midpoint)).exp()),
+            ColorCorrection::Linear => (value - midpoint) * contrast + midpoint,
+            ColorCorrection::Gamma => value.powf(gamma),
+        };
+
+        *output = (value * 255.0).round().clamp(0.0, 255.0) as u8;
+    }
+
+    table
+}
+
+/// Applies the lookup table to all color channels of the interleaved pixels, keeping the last (alpha) channel.
+pub fn apply(pixels: &mut [u8], band_count: usize, table: &[u8; 256]) {
+    for pixel in pixels.chunks_exact_mut(band_count) {
+        for value in &mut pixel[..band_count - 1] {
+            *value = table[*value as usize];
+        }
+    }
+}
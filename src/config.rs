@@ -0,0 +1,76 @@
+use crate::args::{Args, Cli, Format};
+use clap::{CommandFactory, Parser, error::ErrorKind};
+use std::{ffi::OsString, path::PathBuf};
+
+/// Builder of the tiling arguments for embedding the tiler, with the defaults of the command line.
+pub struct TilerConfig {
+    args: Args,
+}
+
+impl TilerConfig {
+    /// Returns config of tiling the source file to the target file up to the max zoom, or the
+    /// error of parsing the paths as the command line arguments.
+    pub fn new(
+        source_file: impl Into<PathBuf>,
+        target_file: impl Into<PathBuf>,
+        max_zoom: u8,
+    ) -> Result<Self, clap::Error> {
+        let cli = Cli::try_parse_from([
+            OsString::from(env!("CARGO_PKG_NAME")),
+            "--source-file".into(),
+            source_file.into().into(),
+            "--target-file".into(),
+            target_file.into().into(),
+            "--max-zoom".into(),
+            max_zoom.to_string().into(),
+        ])?;
+
+        let args = cli.args.ok_or_else(|| {
+            Cli::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "args should be present without a subcommand",
+            )
+        })?;
+
+        Ok(Self { args })
+    }
+
+    pub fn min_zoom(mut self, min_zoom: u8) -> Self {
+        self.args.min_zoom = min_zoom;
+
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u16) -> Self {
+        self.args.tile_size = tile_size;
+
+        self
+    }
+
+    pub fn format(mut self, format: Format) -> Self {
+        self.args.format = format;
+
+        self
+    }
+
+    pub fn num_threads(mut self, num_threads: u16) -> Self {
+        self.args.num_threads = Some(num_threads);
+
+        self
+    }
+
+    pub fn bounding_polygon(mut self, bounding_polygon: impl Into<PathBuf>) -> Self {
+        self.args.bounding_polygon = Some(bounding_polygon.into());
+
+        self
+    }
+
+    /// Returns the arguments to set the ones without a builder method.
+    pub fn args_mut(&mut self) -> &mut Args {
+        &mut self.args
+    }
+
+    pub fn build(self) -> Args {
+        self.args
+    }
+}
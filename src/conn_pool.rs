@@ -0,0 +1,81 @@
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    ops::Deref,
+    path::Path,
+    sync::{Condvar, Mutex},
+};
+
+/// Bounded pool of read-only connections to the same database
+pub struct ConnectionPool {
+    connections: Mutex<Vec<Connection>>,
+    available: Condvar,
+}
+
+impl ConnectionPool {
+    /// Opens `size` read-only connections to the database.
+    pub fn open(path: &Path, size: usize) -> rusqlite::Result<Self> {
+        let connections = (0..size.max(1))
+            .map(|_| {
+                Connection::open_with_flags(
+                    path,
+                    OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            connections: Mutex::new(connections),
+            available: Condvar::new(),
+        })
+    }
+
+    /// Takes a connection from the pool, waiting until one is returned if all are in use.
+    pub fn get(&self) -> PooledConnection<'_> {
+        let mut connections = self
+            .connections
+            .lock()
+            .expect("connection pool should be locked");
+
+        loop {
+            if let Some(conn) = connections.pop() {
+                return PooledConnection {
+                    pool: self,
+                    conn: Some(conn),
+                };
+            }
+
+            connections = self
+                .available
+                .wait(connections)
+                .expect("connection pool should be locked");
+        }
+    }
+}
+
+/// Connection returned to the pool on drop
+pub struct PooledConnection<'a> {
+    pool: &'a ConnectionPool,
+    conn: Option<Connection>,
+}
+
+impl Deref for PooledConnection<'_> {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.conn.as_ref().expect("connection should be present")
+    }
+}
+
+impl Drop for PooledConnection<'_> {
+    fn drop(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            self.pool
+                .connections
+                .lock()
+                .expect("connection pool should be locked")
+                .push(conn);
+
+            self.pool.available.notify_one();
+        }
+    }
+}
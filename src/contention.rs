@@ -0,0 +1,47 @@
+use std::sync::{
+    Mutex, MutexGuard, TryLockError,
+    atomic::{AtomicUsize, Ordering},
+};
+
+/// Mutex counting how often locking it had to wait for another thread
+pub struct MonitoredMutex<T> {
+    name: &'static str,
+    mutex: Mutex<T>,
+    accesses: AtomicUsize,
+    contentions: AtomicUsize,
+}
+
+impl<T> MonitoredMutex<T> {
+    pub fn new(name: &'static str, value: T) -> Self {
+        Self {
+            name,
+            mutex: Mutex::new(value),
+            accesses: AtomicUsize::new(0),
+            contentions: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        self.accesses.fetch_add(1, Ordering::Relaxed);
+
+        match self.mutex.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::WouldBlock) => {
+                self.contentions.fetch_add(1, Ordering::Relaxed);
+
+                self.mutex
+                    .lock()
+                    .unwrap_or_else(|_| panic!("error locking {}", self.name))
+            }
+            Err(TryLockError::Poisoned(_)) => panic!("error locking {}", self.name),
+        }
+    }
+
+    /// Returns number of contended and all accesses.
+    pub fn contention(&self) -> (usize, usize) {
+        (
+            self.contentions.load(Ordering::Relaxed),
+            self.accesses.load(Ordering::Relaxed),
+        )
+    }
+}
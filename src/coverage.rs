@@ -0,0 +1,45 @@
+use crate::{error::TilerError, geo::web_mercator_to_wgs84};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use tilemath::Tile;
+
+/// Writes GeoJSON FeatureCollection of the tile polygons in EPSG:4326 with `zoom`, `x` and `y`
+/// properties. Features are streamed to the file one by one.
+pub fn write_coverage_geojson(
+    path: &Path,
+    tiles: &[Tile],
+    tile_size: u16,
+) -> Result<(), TilerError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+
+    writer.write_all(br#"{"type":"FeatureCollection","features":["#)?;
+
+    for (i, tile) in tiles.iter().enumerate() {
+        let bbox = tile.bounds(tile_size);
+
+        let (min_lon, min_lat) = web_mercator_to_wgs84(bbox.min_x, bbox.min_y);
+
+        let (max_lon, max_lat) = web_mercator_to_wgs84(bbox.max_x, bbox.max_y);
+
+        if i > 0 {
+            writer.write_all(b",")?;
+        }
+
+        write!(
+            writer,
+            r#"{{"type":"Feature","properties":{{"zoom":{},"x":{},"y":{}}},"geometry":{{"type":"Polygon","coordinates":[[[{min_lon},{min_lat}],[{max_lon},{min_lat}],[{max_lon},{max_lat}],[{min_lon},{max_lat}],[{min_lon},{min_lat}]]]}}}}"#,
+            tile.zoom, tile.x, tile.y
+        )?;
+
+        writer.write_all(b"\n")?;
+    }
+
+    writer.write_all(b"]}\n")?;
+
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+use tilemath::Tile;
+
+/// Megatile to save for debugging before it is sliced into tiles
+pub struct DebugMegatile {
+    pub tile: Tile,
+    /// Path prefix of the written PNG files
+    pub prefix: PathBuf,
+    /// Also write the alpha channel as a separate grayscale PNG
+    pub alpha: bool,
+}
+
+impl DebugMegatile {
+    /// Writes the megatile pixels as PNG with the geo-transform in the `iTXt` chunk.
+    /// The `mega_size` includes the `padding` on every side.
+    pub fn write(
+        &self,
+        megatile: &[u8],
+        mega_size: u16,
+        padding: u16,
+        band_count: usize,
+    ) -> Result<(), png::EncodingError> {
+        let bbox = self.tile.bounds(mega_size - 2 * padding);
+
+        let resolution = (bbox.max_x - bbox.min_x) / f64::from(mega_size - 2 * padding);
+
+        let offset = f64::from(padding) * resolution;
+
+        let geo_transform = format!(
+            "{}, {resolution}, 0, {}, 0, {}",
+            bbox.min_x - offset,
+            bbox.max_y + offset,
+            -resolution
+        );
+
+        let name = format!(
+            "{}_megatile_z{}_x{}_y{}",
+            self.prefix.display(),
+            self.tile.zoom,
+            self.tile.x,
+            self.tile.y
+        );
+
+        write_png(
+            Path::new(&format!("{name}.png")),
+            megatile,
+            mega_size,
+            if band_count == 2 {
+                png::ColorType::GrayscaleAlpha
+            } else {
+                png::ColorType::Rgba
+            },
+            &geo_transform,
+        )?;
+
+        if self.alpha {
+            let alpha: Vec<_> = megatile
+                .chunks_exact(band_count)
+                .map(|pixel| pixel[band_count - 1])
+                .collect();
+
+            write_png(
+                Path::new(&format!("{name}_alpha.png")),
+                &alpha,
+                mega_size,
+                png::ColorType::Grayscale,
+                &geo_transform,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn write_png(
+    path: &Path,
+    data: &[u8],
+    size: u16,
+    color_type: png::ColorType,
+    geo_transform: &str,
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        u32::from(size),
+        u32::from(size),
+    );
+
+    encoder.set_color(color_type);
+
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.add_itxt_chunk("GeoTransform".to_string(), geo_transform.to_string())?;
+
+    let mut writer = encoder.write_header()?;
+
+    writer.write_image_data(data)?;
+
+    writer.finish()
+}
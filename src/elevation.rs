@@ -0,0 +1,172 @@
+/// Encoding of elevation to RGB
+#[derive(Clone, Copy, Debug)]
+pub enum ElevationEncoding {
+    /// Mapbox Terrain-RGB, `base_offset + (r * 256 * 256 + g * 256 + b) * scale`
+    TerrainRgb {
+        /// Elevation of the encoded value 0 in meters
+        base_offset: f64,
+        /// Elevation step of the encoded value in meters
+        scale: f64,
+    },
+    /// Terrarium, `r * 256 + g + b / 256 - 32768`
+    Terrarium,
+}
+
+/// Max value encodable in 24 bits
+const MAX_VALUE: f64 = ((1 << 24) - 1) as f64;
+
+/// Offset of the Terrarium encoded elevation in meters
+const TERRARIUM_OFFSET: f64 = 32768.0;
+
+impl ElevationEncoding {
+    /// Encodes the elevation to RGB, clamping it to the encodable range.
+    pub fn encode(&self, elevation: f64) -> [u8; 3] {
+        let value = match *self {
+            Self::TerrainRgb { base_offset, scale } => ((elevation - base_offset) / scale).round(),
+            // fixed point value with 8 fractional bits
+            Self::Terrarium => ((elevation + TERRARIUM_OFFSET) * 256.0).round(),
+        }
+        .clamp(0.0, MAX_VALUE) as u32;
+
+        [(value >> 16) as u8, (value >> 8) as u8, value as u8]
+    }
+
+    pub fn decode(&self, rgb: &[u8]) -> f64 {
+        let value = (u32::from(rgb[0]) << 16) | (u32::from(rgb[1]) << 8) | u32::from(rgb[2]);
+
+        match *self {
+            Self::TerrainRgb { base_offset, scale } => base_offset + f64::from(value) * scale,
+            Self::Terrarium => f64::from(value) / 256.0 - TERRARIUM_OFFSET,
+        }
+    }
+
+    /// Encodes the elevations to packed RGB.
+    pub fn encode_all(&self, elevations: &[f32]) -> Vec<u8> {
+        elevations
+            .iter()
+            .flat_map(|&elevation| self.encode(f64::from(elevation)))
+            .collect()
+    }
+
+    /// Downsamples RGBA buffer of the encoded elevations of size `2 * size` to `size` by averaging
+    /// elevations of the valid (non-transparent) pixels of every 2×2 block. Blocks without valid
+    /// pixels are transparent with the RGB `0, 0, 0`.
+    pub fn downsample(&self, buffer: &[u8], size: usize) -> Vec<u8> {
+        let mut result = vec![0; size * size * 4];
+
+        for y in 0..size {
+            for x in 0..size {
+                let mut sum = 0.0;
+
+                let mut count = 0;
+
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let offset = ((x * 2 + dx) + (y * 2 + dy) * size * 2) * 4;
+
+                    if buffer[offset + 3] > 0 {
+                        sum += self.decode(&buffer[offset..offset + 3]);
+
+                        count += 1;
+                    }
+                }
+
+                if count > 0 {
+                    let offset = (x + y * size) * 4;
+
+                    result[offset..offset + 3]
+                        .copy_from_slice(&self.encode(sum / f64::from(count)));
+
+                    result[offset + 3] = 255;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TERRAIN_RGB: ElevationEncoding = ElevationEncoding::TerrainRgb {
+        base_offset: -10000.0,
+        scale: 0.1,
+    };
+
+    #[test]
+    fn terrain_rgb() {
+        assert_eq!(TERRAIN_RGB.encode(0.0), [1, 134, 160]);
+        assert_eq!(TERRAIN_RGB.decode(&[1, 134, 160]), 0.0);
+
+        for elevation in [-10000.0, -412.3, 0.0, 1234.5, 8848.8] {
+            assert!((TERRAIN_RGB.decode(&TERRAIN_RGB.encode(elevation)) - elevation).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn terrarium() {
+        let terrarium = ElevationEncoding::Terrarium;
+
+        assert_eq!(terrarium.encode(0.0), [128, 0, 0]);
+        assert_eq!(terrarium.decode(&[128, 0, 0]), 0.0);
+
+        for elevation in [-32768.0, -412.25, 0.0, 1234.5, 8848.75] {
+            assert_eq!(terrarium.decode(&terrarium.encode(elevation)), elevation);
+        }
+    }
+
+    #[test]
+    fn clamping() {
+        assert_eq!(TERRAIN_RGB.encode(-20000.0), [0, 0, 0]);
+        assert_eq!(TERRAIN_RGB.encode(2e6), [255, 255, 255]);
+
+        assert_eq!(ElevationEncoding::Terrarium.encode(-40000.0), [0, 0, 0]);
+        assert_eq!(
+            ElevationEncoding::Terrarium.encode(40000.0),
+            [255, 255, 255]
+        );
+    }
+
+    #[test]
+    fn downsample() {
+        let pixel = |elevation, alpha| {
+            let [r, g, b] = TERRAIN_RGB.encode(elevation);
+
+            [r, g, b, alpha]
+        };
+
+        let transparent = pixel(0.0, 0);
+
+        let opaque = pixel(1000.0, 255);
+
+        // the top left block has one transparent pixel, the top right one is fully transparent
+        let buffer: Vec<u8> = [
+            [
+                pixel(100.0, 255),
+                pixel(200.0, 255),
+                transparent,
+                transparent,
+            ],
+            [
+                pixel(300.0, 255),
+                pixel(5000.0, 0),
+                transparent,
+                transparent,
+            ],
+            [opaque; 4],
+            [opaque; 4],
+        ]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect();
+
+        let expected: Vec<u8> = [pixel(200.0, 255), [0, 0, 0, 0], opaque, opaque]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        assert_eq!(TERRAIN_RGB.downsample(&buffer, 2), expected);
+    }
+}
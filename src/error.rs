@@ -0,0 +1,42 @@
+use gdal::errors::GdalError;
+use std::{io, path::PathBuf};
+use thiserror::Error;
+use tilemath::Tile;
+
+#[derive(Debug, Error)]
+pub enum TilerError {
+    #[error("Source file not found: {}", .0.display())]
+    SourceNotFound(PathBuf),
+    #[error("Target file exists: {}", .0.display())]
+    TargetExists(PathBuf),
+    #[error("Unsupported raster with {0} band(s), supports only G, GA, RGB, RGBA rasters")]
+    InvalidBandCount(usize),
+    #[error("Invalid spatial reference: {0}")]
+    InvalidSpatialReference(String),
+    #[error("Warping of {tile} failed with error code: {code}")]
+    WarpFailed { tile: Tile, code: i32 },
+    #[error("Error reading GeoJSON: {0}")]
+    GeoJsonParseError(String),
+    #[error("Error reprojecting: {0}")]
+    ReprojectionError(String),
+    #[error("Invalid metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("Invalid source file list: {0}")]
+    InvalidSourceFileList(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+    #[error("Source file modified during tiling: {}", .0.display())]
+    SourceModified(PathBuf),
+    #[error("{0} health check(s) failed")]
+    HealthCheckFailed(usize),
+    #[error("Interrupted, partial output written to {}", .0.display())]
+    Interrupted(PathBuf),
+    #[error("GDAL error: {0}")]
+    Gdal(#[from] GdalError),
+    #[error("Error accessing output: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+}
@@ -0,0 +1,46 @@
+use geo::{BoundingRect, Intersects, Polygon};
+use rstar::{
+    AABB, RTree,
+    primitives::{GeomWithData, Rectangle},
+};
+
+/// R-tree of bounding rectangles of the exclusion polygons for fast tile intersection test
+pub struct ExclusionIndex {
+    tree: RTree<GeomWithData<Rectangle<[f64; 2]>, usize>>,
+    polygons: Vec<Polygon>,
+}
+
+impl ExclusionIndex {
+    pub fn new(polygons: Vec<Polygon>) -> Self {
+        let tree = RTree::bulk_load(
+            polygons
+                .iter()
+                .enumerate()
+                .filter_map(|(i, polygon)| {
+                    let rect = polygon.bounding_rect()?;
+
+                    Some(GeomWithData::new(
+                        Rectangle::from_corners(rect.min().into(), rect.max().into()),
+                        i,
+                    ))
+                })
+                .collect(),
+        );
+
+        Self { tree, polygons }
+    }
+
+    /// Returns true if the polygon (eg. of a tile) intersects any of the exclusion polygons.
+    pub fn intersects(&self, polygon: &Polygon) -> bool {
+        let Some(rect) = polygon.bounding_rect() else {
+            return false;
+        };
+
+        self.tree
+            .locate_in_envelope_intersecting(&AABB::from_corners(
+                rect.min().into(),
+                rect.max().into(),
+            ))
+            .any(|candidate| self.polygons[candidate.data].intersects(polygon))
+    }
+}
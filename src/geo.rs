@@ -1,5 +1,165 @@
-use gdal::Dataset;
-use tilemath::BBox;
+use gdal::{
+    Dataset, GeoTransformEx,
+    errors::GdalError,
+    spatial_ref::{AxisMappingStrategy, CoordTransform, SpatialRef},
+};
+use gdal_sys::{OGRErr, OSRExportToWktEx, VSIFree};
+use geo::{LineString, Polygon};
+use std::{ffi::CStr, ptr};
+use tilemath::{BBox, WEB_MERCATOR_EXTENT};
+
+/// PROJ pipeline from EPSG:4326 (in GIS axis order) to EPSG:3857
+pub const WGS84_TO_WEB_MERCATOR_PIPELINE: &str = "+proj=pipeline +step +proj=unitconvert +xy_in=deg +xy_out=rad +step +proj=webmerc +lat_0=0 +lon_0=0 +x_0=0 +y_0=0 +ellps=WGS84";
+
+#[derive(Debug, Clone, Copy)]
+pub struct BandStatistics {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub std_dev: f64,
+}
+
+/// Computes (approximate) statistics of every band of the dataset
+pub fn compute_band_statistics(dataset: &Dataset) -> Result<Vec<BandStatistics>, GdalError> {
+    dataset
+        .rasterbands()
+        .map(|band| {
+            let stats = band?.get_statistics(true, true)?;
+
+            Ok(stats.map_or(
+                BandStatistics {
+                    min: f64::NAN,
+                    max: f64::NAN,
+                    mean: f64::NAN,
+                    std_dev: f64::NAN,
+                },
+                |stats| BandStatistics {
+                    min: stats.min,
+                    max: stats.max,
+                    mean: stats.mean,
+                    std_dev: stats.std_dev,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Returns WKT2 representation of the SRS
+pub fn to_wkt2(srs: &SpatialRef) -> Result<String, GdalError> {
+    let options = [c"FORMAT=WKT2".as_ptr(), ptr::null()];
+
+    let mut c_wkt = ptr::null_mut();
+
+    unsafe {
+        let rv = OSRExportToWktEx(srs.to_c_hsrs(), &mut c_wkt, options.as_ptr());
+
+        let result = if rv == OGRErr::OGRERR_NONE {
+            Ok(CStr::from_ptr(c_wkt).to_string_lossy().into_owned())
+        } else {
+            Err(GdalError::OgrError {
+                err: rv,
+                method_name: "OSRExportToWktEx",
+            })
+        };
+
+        VSIFree(c_wkt.cast());
+
+        result
+    }
+}
+
+/// Returns true if the SRS is geographic WGS 84 (EPSG:4326)
+pub fn is_wgs84(srs: &SpatialRef) -> bool {
+    srs.auth_code().is_ok_and(|code| code == 4326)
+        || (srs.is_geographic() && srs.name().is_some_and(|name| name == "WGS 84"))
+}
+
+/// Transforms EPSG:3857 bounds to EPSG:4326 bounds in the longitude, latitude order.
+pub fn to_wgs84_bounds(bounds: &[f64; 4]) -> Result<[f64; 4], GdalError> {
+    let mut target_srs = SpatialRef::from_epsg(4326)?;
+
+    target_srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+
+    CoordTransform::new(&SpatialRef::from_epsg(3857)?, &target_srs)?.transform_bounds(bounds, 21)
+}
+
+/// Transforms EPSG:3857 coordinates to EPSG:4326 longitude and latitude.
+pub fn web_mercator_to_wgs84(x: f64, y: f64) -> (f64, f64) {
+    (
+        x / WEB_MERCATOR_EXTENT * 180.0,
+        (y / WEB_MERCATOR_EXTENT * std::f64::consts::PI)
+            .sinh()
+            .atan()
+            .to_degrees(),
+    )
+}
+
+/// Returns WKT multipolygon of the EPSG:4326 polygons in pixel coordinates of the source dataset in
+/// the source SRS, as expected by the `CUTLINE` warp option.
+pub fn cutline_wkt(
+    polygons: &[Polygon],
+    source_srs: &SpatialRef,
+    source_ds: &Dataset,
+) -> Result<String, GdalError> {
+    let mut wgs84_srs = SpatialRef::from_epsg(4326)?;
+
+    wgs84_srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+
+    let mut source_srs = source_srs.clone();
+
+    source_srs.set_axis_mapping_strategy(AxisMappingStrategy::TraditionalGisOrder);
+
+    let coord_transform = CoordTransform::new(&wgs84_srs, &source_srs)?;
+
+    let inverse_geo_transform = source_ds.geo_transform()?.invert()?;
+
+    let ring_wkt = |ring: &LineString| -> Result<String, GdalError> {
+        let (mut xs, mut ys): (Vec<_>, Vec<_>) = ring.coords().map(|c| (c.x, c.y)).unzip();
+
+        coord_transform.transform_coords(&mut xs, &mut ys, &mut [])?;
+
+        let coords: Vec<_> = xs
+            .into_iter()
+            .zip(ys)
+            .map(|(x, y)| {
+                let (pixel, line) = inverse_geo_transform.apply(x, y);
+
+                format!("{pixel} {line}")
+            })
+            .collect();
+
+        Ok(format!("({})", coords.join(", ")))
+    };
+
+    let polygons = polygons
+        .iter()
+        .map(|polygon| {
+            let rings = std::iter::once(polygon.exterior())
+                .chain(polygon.interiors())
+                .map(ring_wkt)
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(format!("({})", rings.join(", ")))
+        })
+        .collect::<Result<Vec<_>, GdalError>>()?;
+
+    Ok(format!("MULTIPOLYGON ({})", polygons.join(", ")))
+}
+
+/// Returns true if EPSG:3857 bounds are finite and don't span most of the world
+pub fn is_plausible_extent(bounds: &[f64; 4]) -> bool {
+    bounds.iter().all(|c| c.is_finite())
+        && bounds[2] - bounds[0] < WEB_MERCATOR_EXTENT
+        && bounds[3] - bounds[1] < WEB_MERCATOR_EXTENT
+}
+
+/// Returns true if EPSG:3857 coordinates are finite and within the Web Mercator world extent
+pub fn is_within_web_mercator(x: f64, y: f64) -> bool {
+    x.is_finite()
+        && y.is_finite()
+        && x.abs() <= WEB_MERCATOR_EXTENT
+        && y.abs() <= WEB_MERCATOR_EXTENT
+}
 
 pub fn compute_bbox(dataset: &Dataset) -> BBox {
     let geo_transform = dataset.geo_transform().unwrap();
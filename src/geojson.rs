@@ -1,55 +1,80 @@
-use geo::Polygon;
+use crate::error::TilerError;
+use geo::{MultiPolygon, Polygon};
 use geojson::GeoJson;
 use proj::{Proj, Transform};
-use std::fs::File;
-use std::io::Read;
 use std::path::Path;
+use tilemath::BBox;
 
-// Read GeoJSON and parse into a Polygon
-pub fn parse_geojson_polygon(file_path: &Path) -> Result<Polygon<f64>, String> {
-    let mut file = File::open(file_path).map_err(|e| format!("Failed to open file: {e}"))?;
-
-    let mut geojson_str = String::new();
-
-    file.read_to_string(&mut geojson_str)
-        .map_err(|e| format!("Failed to read file: {e}"))?;
+// Read GeoJSON and parse all its polygons, including parts of multipolygons
+pub fn parse_geojson_polygons(file_path: &Path) -> Result<Vec<Polygon<f64>>, TilerError> {
+    let geojson_str = std::fs::read_to_string(file_path).map_err(|e| {
+        TilerError::GeoJsonParseError(format!("Failed to read {}: {e}", file_path.display()))
+    })?;
 
     let geojson: GeoJson = geojson_str
         .parse()
-        .map_err(|e| format!("Invalid GeoJSON: {e}"))?;
-
-    match geojson {
-        GeoJson::Feature(feature) => {
-            if let Some(geometry) = feature.geometry {
-                Polygon::try_from(geometry.value).map_err(|_| "No polygon found".into())
-            } else {
-                Err("Feature has no geometry".into())
-            }
-        }
-        GeoJson::FeatureCollection(collection) => {
-            for feature in collection.features {
-                if let Some(geometry) = feature.geometry
-                    && let Ok(polygon) = Polygon::try_from(geometry.value)
-                {
-                    return Ok(polygon);
-                }
-            }
-            Err("No polygons found in collection".into())
+        .map_err(|e| TilerError::GeoJsonParseError(format!("Invalid GeoJSON: {e}")))?;
+
+    let geometries = match geojson {
+        GeoJson::Feature(feature) => feature.geometry.into_iter().collect(),
+        GeoJson::FeatureCollection(collection) => collection
+            .features
+            .into_iter()
+            .filter_map(|feature| feature.geometry)
+            .collect(),
+        GeoJson::Geometry(geometry) => vec![geometry],
+    };
+
+    let mut polygons = Vec::new();
+
+    for geometry in geometries {
+        if let Ok(polygon) = Polygon::try_from(geometry.value.clone()) {
+            polygons.push(polygon);
+        } else if let Ok(multi_polygon) = MultiPolygon::try_from(geometry.value) {
+            polygons.extend(multi_polygon);
         }
-        GeoJson::Geometry(_) => Err("GeoJSON does not contain features".into()),
     }
+
+    if polygons.is_empty() {
+        return Err(TilerError::GeoJsonParseError("No polygons found".into()));
+    }
+
+    Ok(polygons)
 }
 
-// Reproject a Polygon from EPSG:4326 to EPSG:3857 using geo's Transform
-pub fn reproject_polygon(polygon: &mut Polygon<f64>) -> Result<(), String> {
+// Reproject Polygons from EPSG:4326 to EPSG:3857 using geo's Transform
+pub fn reproject_polygons(polygons: &mut Vec<Polygon<f64>>) -> Result<(), TilerError> {
     // Create a Proj instance for EPSG:4326 -> EPSG:3857
     let proj = Proj::new_known_crs("EPSG:4326", "EPSG:3857", None)
-        .map_err(|e| format!("Failed to create projection: {e}"))?;
+        .map_err(|e| TilerError::ReprojectionError(format!("Failed to create projection: {e}")))?;
 
-    // Use geo's Transform trait to reproject the polygon
-    polygon
-        .transform(&proj)
-        .map_err(|e| format!("Reprojection failed: {e}"))?;
+    // Use geo's Transform trait to reproject the polygons
+    for polygon in polygons {
+        polygon
+            .transform(&proj)
+            .map_err(|e| TilerError::ReprojectionError(e.to_string()))?;
+    }
 
     Ok(())
 }
+
+// Reproject a bounding box `[min_lon, min_lat, max_lon, max_lat]` from EPSG:4326 to EPSG:3857
+pub fn reproject_bbox(bbox: &[f64; 4]) -> Result<BBox, TilerError> {
+    let proj = Proj::new_known_crs("EPSG:4326", "EPSG:3857", None)
+        .map_err(|e| TilerError::ReprojectionError(format!("Failed to create projection: {e}")))?;
+
+    let (min_x, min_y) = proj
+        .convert((bbox[0], bbox[1]))
+        .map_err(|e| TilerError::ReprojectionError(e.to_string()))?;
+
+    let (max_x, max_y) = proj
+        .convert((bbox[2], bbox[3]))
+        .map_err(|e| TilerError::ReprojectionError(e.to_string()))?;
+
+    Ok(BBox {
+        min_x,
+        max_x,
+        min_y,
+        max_y,
+    })
+}
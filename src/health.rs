@@ -0,0 +1,152 @@
+use crate::{
+    error::TilerError,
+    source_list::{is_vsi_path, open_source},
+};
+use gdal::{DriverManager, spatial_ref::SpatialRef, version::VersionInfo};
+use std::{env, ffi::CString, fs, path::Path, thread};
+
+/// GDAL drivers used by the tiler
+const REQUIRED_DRIVERS: [&str; 3] = ["GTiff", "MEM", "VRT"];
+
+/// Free space below which the disk space check fails
+const MIN_FREE_SPACE_BYTES: i64 = 1 << 30;
+
+/// Prints the check result and returns whether it passed.
+fn report(name: &str, result: Result<String, String>) -> bool {
+    match result {
+        Ok(message) => {
+            println!("[PASS] {name}: {message}");
+
+            true
+        }
+        Err(message) => {
+            println!("[FAIL] {name}: {message}");
+
+            false
+        }
+    }
+}
+
+fn check_gdal() -> Result<String, String> {
+    let missing: Vec<_> = REQUIRED_DRIVERS
+        .iter()
+        .filter(|name| DriverManager::get_driver_by_name(name).is_err())
+        .copied()
+        .collect();
+
+    let summary = format!(
+        "{}, {} drivers",
+        VersionInfo::version_summary(),
+        DriverManager::count()
+    );
+
+    if missing.is_empty() {
+        Ok(summary)
+    } else {
+        Err(format!(
+            "{summary}, missing required {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+fn check_proj() -> Result<String, String> {
+    let proj_data = ["PROJ_DATA", "PROJ_LIB"]
+        .iter()
+        .find_map(|name| env::var(name).ok().map(|value| format!("{name}={value}")))
+        .unwrap_or_else(|| "default PROJ data directory".to_string());
+
+    SpatialRef::from_epsg(3857)
+        .and_then(|srs| srs.to_wkt())
+        .map(|_| format!("EPSG database found using {proj_data}"))
+        .map_err(|e| {
+            format!("EPSG:3857 can't be resolved using {proj_data}, set PROJ_DATA to directory with proj.db: {e}")
+        })
+}
+
+fn check_source(path: &Path) -> Result<String, String> {
+    if !is_vsi_path(path) && !path.exists() {
+        return Err(format!("{} not found", path.display()));
+    }
+
+    let source_ds = open_source(path, 0, None).map_err(|e| format!("error opening: {e}"))?;
+
+    let (width, height) = source_ds.raster_size();
+
+    let summary = format!(
+        "{width}×{height} pixels, {} band(s)",
+        source_ds.raster_count()
+    );
+
+    if source_ds.projection().is_empty() {
+        return Err(format!(
+            "{summary}, no CRS; specify it with --source-srs or --source-epsg"
+        ));
+    }
+
+    if source_ds.geo_transform().is_err() {
+        return Err(format!("{summary}, no geo-transform"));
+    }
+
+    Ok(summary)
+}
+
+fn check_output(dir: &Path) -> Result<String, String> {
+    let probe = dir.join(".freemap-tiler-health-check");
+
+    fs::write(&probe, [])
+        .and_then(|()| fs::remove_file(&probe))
+        .map_err(|e| format!("{} is not writable: {e}", dir.display()))?;
+
+    let c_dir = CString::new(dir.to_string_lossy().as_bytes())
+        .map_err(|_| "path contains NUL".to_string())?;
+
+    let free = unsafe { gdal_sys::VSIGetDiskFreeSpace(c_dir.as_ptr()) };
+
+    if free < 0 {
+        return Ok(format!("{} is writable, free space unknown", dir.display()));
+    }
+
+    let message = format!(
+        "{} is writable, {:.1} GiB free",
+        dir.display(),
+        free as f64 / f64::from(1 << 30)
+    );
+
+    if free < MIN_FREE_SPACE_BYTES {
+        Err(message)
+    } else {
+        Ok(message)
+    }
+}
+
+/// Checks the environment (GDAL, PROJ, SQLite, CPU, output directory and optionally the source)
+/// and prints result of every check.
+pub fn health_check(source_file: Option<&Path>, output_dir: &Path) -> Result<(), TilerError> {
+    let mut passed = vec![
+        report("GDAL", check_gdal()),
+        report("PROJ", check_proj()),
+        report("SQLite", Ok(format!("{} (bundled)", rusqlite::version()))),
+        report(
+            "CPU",
+            thread::available_parallelism()
+                .map(|threads| format!("{threads} thread(s) available"))
+                .map_err(|e| format!("unknown number of threads: {e}")),
+        ),
+        report("Output", check_output(output_dir)),
+    ];
+
+    if let Some(source_file) = source_file {
+        passed.push(report("Source", check_source(source_file)));
+    }
+
+    let failed = passed.iter().filter(|passed| !**passed).count();
+
+    if failed > 0 {
+        return Err(TilerError::HealthCheckFailed(failed));
+    }
+
+    println!("All checks passed");
+
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use tilemath::Tile;
+
+/// Returns position of the tile on the [Hilbert curve](https://en.wikipedia.org/wiki/Hilbert_curve)
+/// covering its zoom level, as in the PMTiles tile IDs.
+pub fn hilbert_index(tile: &Tile) -> u64 {
+    let n = 1_u64 << tile.zoom;
+
+    let mut x = u64::from(tile.x);
+
+    let mut y = u64::from(tile.y);
+
+    let mut d = 0;
+
+    let mut s = n / 2;
+
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+
+        let ry = u64::from(y & s > 0);
+
+        d += s * s * ((3 * rx) ^ ry);
+
+        // rotate the quadrant
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+
+            std::mem::swap(&mut x, &mut y);
+        }
+
+        s /= 2;
+    }
+
+    d
+}
+
+/// Sort tiles according to the Hilbert index. Like `sort_by_zorder`, it does not take the zoom into the account.
+pub fn sort_by_hilbert(tiles: &mut [Tile]) {
+    tiles.sort_by_cached_key(hilbert_index);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_is_first() {
+        assert_eq!(
+            hilbert_index(&Tile {
+                zoom: 0,
+                x: 0,
+                y: 0
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn zoom_1_follows_the_curve() {
+        let indices: Vec<_> = [(0, 0), (0, 1), (1, 1), (1, 0)]
+            .into_iter()
+            .map(|(x, y)| hilbert_index(&Tile { zoom: 1, x, y }))
+            .collect();
+
+        assert_eq!(indices, [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn indices_are_unique_within_zoom() {
+        let mut indices: Vec<_> = (0..8)
+            .flat_map(|x| (0..8).map(move |y| hilbert_index(&Tile { zoom: 3, x, y })))
+            .collect();
+
+        indices.sort_unstable();
+
+        assert_eq!(indices, (0..64).collect::<Vec<_>>());
+    }
+}
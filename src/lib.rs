@@ -0,0 +1,68 @@
+//! Tiling of raster geofiles into MBTiles, PMTiles or directories of tiles in EPSG:3857.
+//!
+//! [`run`] generates the tiles of the [`args::Args`] parsed by the `freemap-tiler` binary or built
+//! by [`TilerConfig`] with the defaults of the command line. Embedders can also use the modules
+//! directly, eg. [`warp::warp`] to warp a source into a tile dataset, [`Processor`] to render and
+//! compose the tiles and [`tile_inserter::new`] to store them.
+
+pub mod args;
+mod avif_tile;
+pub mod bbox;
+mod blend;
+mod checksum;
+pub mod clip;
+pub mod color;
+pub mod config;
+mod conn_pool;
+mod contention;
+pub mod coverage;
+pub mod debug_megatile;
+pub mod elevation;
+pub mod error;
+pub mod exclusion;
+pub mod geo;
+pub mod geojson;
+pub mod health;
+pub mod hilbert;
+mod palette;
+pub mod pmtiles;
+pub mod processor;
+pub mod profile;
+pub mod progress;
+mod quantize;
+pub mod recover;
+mod run;
+pub mod schema;
+pub mod source_list;
+pub mod state;
+pub mod tile_checksum;
+mod tile_filter;
+pub mod tile_inserter;
+pub mod tile_path;
+pub mod tile_tree;
+pub mod tilejson;
+pub mod time_track;
+pub mod tune;
+pub mod validate;
+pub mod warp;
+mod webp_tile;
+mod world_file;
+pub mod zorder;
+
+use serde::{Deserialize, Serialize};
+
+pub use args::Format;
+pub use config::TilerConfig;
+pub use error::TilerError;
+pub use processor::Processor;
+pub use run::run;
+pub use tilemath::{BBox, Tile};
+
+/// Column and row bounds of the tiles of a zoom level
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Limits {
+    pub min_x: u32,
+    pub max_x: u32,
+    pub min_y: u32,
+    pub max_y: u32,
+}
@@ -0,0 +1,69 @@
+use gdal::{Dataset, raster::ColorInterpretation};
+use std::path::Path;
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns true for a single band raster with colors in the color table.
+pub fn is_palette(source_ds: &Dataset) -> bool {
+    source_ds.raster_count() == 1
+        && source_ds.rasterband(1).is_ok_and(|band| {
+            band.color_interpretation() == ColorInterpretation::PaletteIndex
+                && band.color_table().is_some()
+        })
+}
+
+/// Returns VRT XML of the palette-indexed source expanded to RGBA bands through its color table.
+///
+/// Pixels of the `nodata_index` palette entry are fully transparent.
+pub fn palette_vrt(
+    source_ds: &Dataset,
+    path: &Path,
+    overview_level: u8,
+    nodata_index: Option<u8>,
+) -> String {
+    let (width, height) = source_ds.raster_size();
+
+    let mut vrt = format!(r#"<VRTDataset rasterXSize="{width}" rasterYSize="{height}">"#);
+
+    let projection = source_ds.projection();
+
+    if !projection.is_empty() {
+        vrt.push_str(&format!("<SRS>{}</SRS>", escape_xml(&projection)));
+    }
+
+    if let Ok(geo_transform) = source_ds.geo_transform() {
+        vrt.push_str(&format!(
+            "<GeoTransform>{}</GeoTransform>",
+            geo_transform.map(|c| c.to_string()).join(", ")
+        ));
+    }
+
+    let open_options = if overview_level == 0 {
+        String::new()
+    } else {
+        format!(
+            r#"<OpenOptions><OOI key="OVERVIEW_LEVEL">{}</OOI></OpenOptions>"#,
+            overview_level - 1
+        )
+    };
+
+    let nodata = nodata_index.map_or_else(String::new, |index| format!("<NODATA>{index}</NODATA>"));
+
+    for (component, color) in ["Red", "Green", "Blue", "Alpha"].iter().enumerate() {
+        // pixels matching NODATA are skipped and keep the initial 0, making them transparent
+        vrt.push_str(&format!(
+            r#"<VRTRasterBand dataType="Byte" band="{band}"><ColorInterp>{color}</ColorInterp><ComplexSource><SourceFilename relativeToVRT="0">{path}</SourceFilename>{open_options}<SourceBand>1</SourceBand><ColorTableComponent>{band}</ColorTableComponent>{nodata}</ComplexSource></VRTRasterBand>"#,
+            band = component + 1,
+            path = escape_xml(&path.to_string_lossy()),
+        ));
+    }
+
+    vrt.push_str("</VRTDataset>");
+
+    vrt
+}
@@ -0,0 +1,359 @@
+use crate::{
+    args::TileRowOrder, checksum::sha256_hex, error::TilerError, hilbert::hilbert_index,
+    schema::read_metadata,
+};
+use rusqlite::{Connection, OpenFlags};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::Path,
+};
+use tilemath::Tile;
+
+/// Size of the fixed PMTiles v3 header
+const HEADER_LENGTH: usize = 127;
+
+/// The header and the root directory must fit in the first 16 KiB
+const ROOT_AREA_LENGTH: usize = 16_384;
+
+/// Initial number of entries of a leaf directory, doubled until the root directory fits
+const LEAF_SIZE: usize = 4096;
+
+/// `Compression::None` of the specification
+const COMPRESSION_NONE: u8 = 1;
+
+/// Metadata keys stored in the header instead of the JSON metadata
+const HEADER_METADATA_KEYS: [&str; 4] = ["bounds", "center", "minzoom", "maxzoom"];
+
+/// Directory entry addressing `run_length` consecutive tiles (a leaf directory if 0)
+struct Entry {
+    tile_id: u64,
+    offset: u64,
+    length: u32,
+    run_length: u32,
+}
+
+/// Returns PMTiles tile ID, the position on the Hilbert curve counted over all lower zoom levels.
+pub fn tile_id(tile: &Tile) -> u64 {
+    // number of tiles of the lower zoom levels
+    ((1 << (2 * u32::from(tile.zoom))) - 1) / 3 + hilbert_index(tile)
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+
+        value >>= 7;
+    }
+
+    buf.push(value as u8);
+}
+
+/// Serializes the directory as columns of varints: tile ID deltas, run lengths, lengths and
+/// offsets (0 if contiguous with the previous entry, offset + 1 otherwise).
+fn serialize_directory(entries: &[Entry]) -> Vec<u8> {
+    let mut buf = Vec::new();
+
+    write_varint(&mut buf, entries.len() as u64);
+
+    let mut last_id = 0;
+
+    for entry in entries {
+        write_varint(&mut buf, entry.tile_id - last_id);
+
+        last_id = entry.tile_id;
+    }
+
+    for entry in entries {
+        write_varint(&mut buf, entry.run_length.into());
+    }
+
+    for entry in entries {
+        write_varint(&mut buf, entry.length.into());
+    }
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 && entry.offset == entries[i - 1].offset + u64::from(entries[i - 1].length) {
+            write_varint(&mut buf, 0);
+        } else {
+            write_varint(&mut buf, entry.offset + 1);
+        }
+    }
+
+    buf
+}
+
+/// Returns the root directory and the leaf directories, splitting the entries into leaves if the
+/// root directory would not fit into the first 16 KiB.
+fn build_directories(entries: &[Entry]) -> (Vec<u8>, Vec<u8>) {
+    let root = serialize_directory(entries);
+
+    if root.len() <= ROOT_AREA_LENGTH - HEADER_LENGTH {
+        return (root, vec![]);
+    }
+
+    let mut leaf_size = LEAF_SIZE;
+
+    loop {
+        let mut leaves = Vec::new();
+
+        let root_entries: Vec<_> = entries
+            .chunks(leaf_size)
+            .map(|chunk| {
+                let leaf = serialize_directory(chunk);
+
+                let entry = Entry {
+                    tile_id: chunk[0].tile_id,
+                    offset: leaves.len() as u64,
+                    length: leaf.len() as u32,
+                    run_length: 0,
+                };
+
+                leaves.extend(leaf);
+
+                entry
+            })
+            .collect();
+
+        let root = serialize_directory(&root_entries);
+
+        if root.len() <= ROOT_AREA_LENGTH - HEADER_LENGTH {
+            return (root, leaves);
+        }
+
+        leaf_size *= 2;
+    }
+}
+
+/// Returns PMTiles tile type of the MBTiles `format` metadata.
+fn tile_type(format: &str) -> u8 {
+    match format {
+        "png" => 2,
+        "jpeg" | "jpg" => 3,
+        "webp" => 4,
+        "avif" => 5,
+        _ => 0,
+    }
+}
+
+/// Parses comma separated numbers of the metadata value.
+fn parse_numbers(value: Option<String>) -> Option<Vec<f64>> {
+    value?.split(',').map(|n| n.trim().parse().ok()).collect()
+}
+
+/// Converts the MBTiles file to PMTiles v3. Tiles are written in the tile ID order with identical
+/// contents stored once and consecutive identical tiles addressed by a single run-length entry.
+/// The MBTiles metadata other than bounds, center and zoom range is stored as JSON metadata.
+pub fn convert_mbtiles(mbtiles: &Path, pmtiles: &Path) -> Result<(), TilerError> {
+    let conn = Connection::open_with_flags(mbtiles, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let tile_row_order = match read_metadata(&conn, "tile_row_order")?.as_deref() {
+        Some("xyz") => TileRowOrder::Xyz,
+        _ => TileRowOrder::Tms,
+    };
+
+    let mut tiles = conn
+        .prepare("SELECT zoom_level, tile_column, tile_row FROM tiles WHERE length(tile_data) > 0")?
+        .query_map([], |row| {
+            let tile = tile_row_order.tile(row.get(0)?, row.get(1)?, row.get(2)?);
+
+            Ok((tile_id(&tile), tile, row.get::<_, u32>(2)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    tiles.sort_unstable_by_key(|(id, _, _)| *id);
+
+    let mut writer = BufWriter::new(File::create(pmtiles)?);
+
+    // the tile data follows the area of the header and the root directory
+    writer.seek(SeekFrom::Start(ROOT_AREA_LENGTH as u64))?;
+
+    let mut stmt = conn.prepare(
+        "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+    )?;
+
+    let mut entries: Vec<Entry> = Vec::new();
+
+    let mut contents = HashMap::<String, (u64, u32)>::new();
+
+    let mut data_length = 0_u64;
+
+    for (id, tile, row) in &tiles {
+        let data: Vec<u8> = stmt.query_row((tile.zoom, tile.x, row), |row| row.get(0))?;
+
+        let (offset, length) = *contents
+            .entry(sha256_hex(&data))
+            .or_insert_with(|| (data_length, data.len() as u32));
+
+        if offset == data_length {
+            writer.write_all(&data)?;
+
+            data_length += u64::from(length);
+        }
+
+        match entries.last_mut() {
+            Some(last)
+                if last.offset == offset && last.tile_id + u64::from(last.run_length) == *id =>
+            {
+                last.run_length += 1;
+            }
+            _ => entries.push(Entry {
+                tile_id: *id,
+                offset,
+                length,
+                run_length: 1,
+            }),
+        }
+    }
+
+    let (root, leaves) = build_directories(&entries);
+
+    let leaves_offset = ROOT_AREA_LENGTH as u64 + data_length;
+
+    writer.write_all(&leaves)?;
+
+    let mut metadata = serde_json::Map::new();
+
+    for row in conn
+        .prepare("SELECT name, value FROM metadata")?
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })?
+    {
+        let (name, value) = row?;
+
+        if !HEADER_METADATA_KEYS.contains(&name.as_str()) {
+            metadata.insert(name, value.into());
+        }
+    }
+
+    let metadata = serde_json::to_vec(&metadata).expect("metadata should be serialized");
+
+    let metadata_offset = leaves_offset + leaves.len() as u64;
+
+    writer.write_all(&metadata)?;
+
+    let bounds = parse_numbers(read_metadata(&conn, "bounds")?)
+        .filter(|bounds| bounds.len() == 4)
+        .unwrap_or_else(|| vec![-180.0, -85.051_128_779_806_59, 180.0, 85.051_128_779_806_59]);
+
+    let center = parse_numbers(read_metadata(&conn, "center")?)
+        .filter(|center| center.len() == 3)
+        .unwrap_or_else(|| {
+            vec![
+                (bounds[0] + bounds[2]) / 2.0,
+                (bounds[1] + bounds[3]) / 2.0,
+                0.0,
+            ]
+        });
+
+    let zoom = |name| -> Result<u8, TilerError> {
+        Ok(read_metadata(&conn, name)?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or_default())
+    };
+
+    let e7 = |coord: f64| ((coord * 10_000_000.0).round() as i32).to_le_bytes();
+
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+
+    header.extend_from_slice(b"PMTiles");
+    header.push(3);
+
+    for value in [
+        HEADER_LENGTH as u64,
+        root.len() as u64,
+        metadata_offset,
+        metadata.len() as u64,
+        leaves_offset,
+        leaves.len() as u64,
+        ROOT_AREA_LENGTH as u64,
+        data_length,
+        tiles.len() as u64,
+        entries.len() as u64,
+        contents.len() as u64,
+    ] {
+        header.extend_from_slice(&value.to_le_bytes());
+    }
+
+    header.push(1); // clustered
+    header.push(COMPRESSION_NONE); // internal compression
+    header.push(COMPRESSION_NONE); // tile compression
+    header.push(tile_type(
+        read_metadata(&conn, "format")?
+            .as_deref()
+            .unwrap_or_default(),
+    ));
+    header.push(zoom("minzoom")?);
+    header.push(zoom("maxzoom")?);
+
+    for coord in &bounds {
+        header.extend_from_slice(&e7(*coord));
+    }
+
+    header.push(center[2] as u8);
+    header.extend_from_slice(&e7(center[0]));
+    header.extend_from_slice(&e7(center[1]));
+
+    writer.seek(SeekFrom::Start(0))?;
+
+    writer.write_all(&header)?;
+
+    writer.write_all(&root)?;
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn root_tile_id_is_zero() {
+        assert_eq!(
+            tile_id(&Tile {
+                zoom: 0,
+                x: 0,
+                y: 0
+            }),
+            0
+        );
+    }
+
+    #[test]
+    fn zoom_1_tile_ids_follow_the_root() {
+        let ids: Vec<_> = [(0, 0), (0, 1), (1, 1), (1, 0)]
+            .into_iter()
+            .map(|(x, y)| tile_id(&Tile { zoom: 1, x, y }))
+            .collect();
+
+        assert_eq!(ids, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn zoom_2_starts_after_zoom_1() {
+        assert_eq!(
+            tile_id(&Tile {
+                zoom: 2,
+                x: 0,
+                y: 0
+            }),
+            5
+        );
+    }
+
+    #[test]
+    fn spec_example() {
+        assert_eq!(
+            tile_id(&Tile {
+                zoom: 12,
+                x: 3423,
+                y: 1763
+            }),
+            19_078_479
+        );
+    }
+}
@@ -1,150 +1,1047 @@
 use crate::{
     Limits,
-    args::Format,
+    args::{
+        AlphaStorage, Format, JpegSubsampling, MtimeCheckAction, ResamplingAlgorithm,
+        SourcePriority, TileRowOrder,
+    },
+    avif_tile, blend,
+    checksum::tile_checksum,
+    color,
+    conn_pool::ConnectionPool,
+    contention::MonitoredMutex,
+    debug_megatile::DebugMegatile,
+    elevation::ElevationEncoding,
+    error::TilerError,
+    quantize,
+    schema::{has_table, read_dictionary},
+    source_list::open_source,
     state::State,
+    tile_filter::TileFilter,
+    tile_inserter::{TileMsg, is_jpeg},
+    tile_path::TilePath,
     time_track::{Metric, StatsMsg},
-    warp::{self, Transform},
+    warp::{self, Transform, WarpMethod, WarpOptions},
+    webp_tile,
 };
 use crossbeam_deque::Worker;
-use gdal::{Dataset, DriverManager, raster::ColorInterpretation};
+use fd_lock::RwLock;
+use gdal::{Dataset, raster::Buffer};
+use hashlink::LruCache;
 use image::{
     GrayAlphaImage, ImageDecoder, ImageEncoder, RgbaImage,
     codecs::{jpeg::JpegDecoder, png::PngEncoder},
     imageops::FilterType,
 };
-use rusqlite::{Connection, OpenFlags};
 use std::sync::Arc;
 use std::{
     collections::{HashMap, HashSet},
-    io::{Cursor, Write},
-    path::{Path, PathBuf},
+    fs::{self, File},
+    io::{Cursor, Read, Write},
+    path::PathBuf,
     sync::{
         Mutex,
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         mpsc::{Sender, SyncSender},
     },
-    time::Instant,
+    time::{Instant, SystemTime},
 };
 use tilemath::Tile;
+use tracing::{debug, warn};
+
+/// Number of processed tiles between sending zoom progress in debug mode
+const ZOOM_PROGRESS_INTERVAL: usize = 1000;
+
+/// Max number of the least recently used buffer cache entries examined for eviction
+const EVICTION_SCAN_LIMIT: usize = 64;
+
+/// Number of processed tiles between sending lock contention
+const CONTENTION_INTERVAL: usize = 1000;
+
+/// Source of the mosaic with its transformation and EPSG:3857 bounds
+pub struct MosaicSource {
+    pub path: PathBuf,
+    /// Overview level to open the source at, 0 is the full resolution
+    pub overview_level: u8,
+    /// Palette entry of transparent pixels of palette-indexed source
+    pub palette_nodata_index: Option<u8>,
+    pub transform: Transform,
+    pub bounds: [f64; 4],
+    /// WKT of the cutline in pixel coordinates of the source
+    pub cutline: Option<String>,
+    /// Zoom levels by which the max zoom exceeds the source resolution, megatiles are warped at the
+    /// source resolution and upscaled
+    pub overzoom: u8,
+    /// Nodata values of the source bands
+    pub no_data: Vec<Option<u8>>,
+}
+
+/// Directory of megatiles warped by previous runs
+pub struct MegatileCache {
+    pub dir: PathBuf,
+    /// Latest modification time of the sources, older cached megatiles are stale
+    pub source_mtime: SystemTime,
+    /// Directory is shared by multiple processes, megatiles are warped under file lock
+    pub shared: bool,
+}
+
+/// Peak sizes of the buffers allocated during the run
+pub struct PeakMemory {
+    pub megatile_bytes: usize,
+    pub out_buffer_bytes: usize,
+    pub buffer_cache_bytes: usize,
+}
+
+/// Periodic check of the source modification during the run
+pub struct MtimeCheck {
+    pub action: MtimeCheckAction,
+    /// Number of warped megatiles between the checks
+    pub interval: usize,
+    /// Modification times of the sources at the start
+    pub mtimes: Vec<(PathBuf, SystemTime)>,
+}
+
+/// Configuration of the tile processing, mostly taken from the arguments
+pub struct ProcessorConfig {
+    pub tile_size: u16,
+    /// Pixels added to every side of the stored tiles
+    pub padding: u16,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+    /// MBTiles file to read the tiles generated by a previous run from
+    pub continue_file: Option<PathBuf>,
+    /// Number of connections reading the continued file
+    pub resume_db_connections: usize,
+    /// Log the processing steps of every tile
+    pub debug: bool,
+    pub source_priority: SourcePriority,
+    pub megatile_cache: Option<MegatileCache>,
+    pub buffer_cache_budget_bytes: usize,
+    pub mtime_check: Option<MtimeCheck>,
+    pub debug_megatile: Option<DebugMegatile>,
+    pub use_mask_band: bool,
+    pub warp_method: WarpMethod,
+    /// Error threshold of the approximated reprojection in pixels
+    pub warp_max_error: f64,
+    pub resampling_algorithm: ResamplingAlgorithm,
+    pub resampling_algorithm_per_zoom: HashMap<u8, ResamplingAlgorithm>,
+    pub warp_memory_mb: f64,
+    pub warp_read_strips: u16,
+    pub jpeg_quality: u8,
+    pub webp_quality: u8,
+    pub avif_quality: u8,
+    pub avif_speed: u8,
+    pub jpeg_subsampling: Option<JpegSubsampling>,
+    pub alpha_compression_level: i32,
+    pub jpeg_restart_interval: u16,
+    /// Zoom levels between the warped megatiles and the max zoom tiles
+    pub zoom_offset: u8,
+    pub insert_empty: bool,
+    pub skip_zoom: Vec<u8>,
+    pub tile_filter: Option<TileFilter>,
+    /// Width in pixels of the blended seams of the composed children, 0 for none
+    pub blend_edges: u16,
+    pub format: Format,
+    pub tile_row_order: TileRowOrder,
+    pub auto_format_threshold: f32,
+    pub alpha_storage: AlphaStorage,
+    pub input_coefficients: Option<Vec<(f32, f32)>>,
+    pub color_table: Option<[u8; 256]>,
+    pub elevation_encoding: Option<ElevationEncoding>,
+    pub alpha_threshold: u8,
+}
+
+/// Channels and state shared by the processor with the rest of the run
+pub struct ProcessorChannels {
+    pub stats_tx: Sender<StatsMsg>,
+    /// Sender of the rendered tiles to the inserter
+    pub data_tx: SyncSender<TileMsg>,
+    /// Sender of the processed tiles not stored by the inserter to the progress file writer
+    pub progress_tx: Option<Sender<Vec<Tile>>>,
+    pub limits: Arc<Mutex<HashMap<u8, Limits>>>,
+    /// Set by SIGINT or SIGTERM to stop processing further tiles
+    pub interrupted: Arc<AtomicBool>,
+}
 
 pub struct Processor {
-    buffer_cache: Arc<Mutex<HashMap<Tile, Vec<u8>>>>,
+    buffer_cache: MonitoredMutex<LruCache<Tile, Vec<u8>>>,
+    buffer_cache_bytes: AtomicUsize,
+    buffer_cache_budget_bytes: usize,
+    buffer_cache_over_budget: AtomicBool,
+    peak_buffer_cache_bytes: AtomicUsize,
+    peak_megatile_size_bytes: AtomicUsize,
+    peak_out_buffer_size_bytes: AtomicUsize,
     tile_size: u16,
+    padding: u16,
+    min_zoom: u8,
     max_zoom: u8,
-    pool: Arc<Mutex<Vec<Dataset>>>,
+    pools: Vec<Mutex<Vec<Dataset>>>,
     counter: AtomicUsize,
+    warp_counter: AtomicUsize,
     total: usize,
-    select_conn: Option<Arc<Mutex<Connection>>>,
+    select_pool: Option<ConnectionPool>,
+    verify_checksums: bool,
+    png_dictionary: Option<Vec<u8>>,
     stats_tx: Sender<StatsMsg>,
     debug: bool,
-    source_file: PathBuf,
-    state: Arc<Mutex<State>>,
-    transform: Transform,
+    sources: Vec<MosaicSource>,
+    source_priority: SourcePriority,
+    megatile_cache: Option<MegatileCache>,
+    mtime_check: Option<MtimeCheck>,
+    source_modified: AtomicBool,
+    /// Set by SIGINT or SIGTERM to stop processing further tiles
+    interrupted: Arc<AtomicBool>,
+    /// First error of the workers, processing is stopped by setting `interrupted`
+    failure: Mutex<Option<TilerError>>,
+    debug_megatile: Option<DebugMegatile>,
+    state: MonitoredMutex<State>,
+    /// Sender of the processed tiles not stored by the inserter to the progress file writer
+    progress_tx: Option<Sender<Vec<Tile>>>,
+    use_mask_band: bool,
+    warp_method: WarpMethod,
+    warp_max_error: f64,
+    resampling_algorithm: ResamplingAlgorithm,
+    resampling_algorithm_per_zoom: HashMap<u8, ResamplingAlgorithm>,
+    warp_memory_mb: f64,
+    warp_read_strips: u16,
     jpeg_quality: u8,
+    webp_quality: u8,
+    avif_quality: u8,
+    avif_speed: u8,
+    jpeg_subsampling: Option<JpegSubsampling>,
+    alpha_compression_level: i32,
+    jpeg_restart_interval: u16,
     limits: Arc<Mutex<HashMap<u8, Limits>>>,
-    data_tx: SyncSender<(Tile, Vec<u8>, Vec<u8>)>,
+    data_tx: SyncSender<TileMsg>,
     zoom_offset: u8,
     insert_empty: bool,
+    skip_zoom: Vec<u8>,
+    tile_filter: Option<TileFilter>,
+    blend_edges: u16,
     format: Format,
+    tile_row_order: TileRowOrder,
+    auto_format_threshold: f32,
+    alpha_storage: AlphaStorage,
+    input_coefficients: Option<Vec<(f32, f32)>>,
+    color_table: Option<[u8; 256]>,
+    elevation_encoding: Option<ElevationEncoding>,
     band_count: usize,
+    alpha_threshold: u8,
 }
 
 impl Processor {
     pub fn new(
-        tile_size: u16,
-        max_zoom: u8,
-        continue_file: Option<&Path>,
-        stats_tx: Sender<StatsMsg>,
-        debug: bool,
-        source_file: &Path,
-        transform: Transform,
-        jpeg_quality: u8,
-        limits: Arc<Mutex<HashMap<u8, Limits>>>,
-        data_tx: SyncSender<(Tile, Vec<u8>, Vec<u8>)>,
+        config: ProcessorConfig,
+        sources: Vec<MosaicSource>,
+        channels: ProcessorChannels,
         pending_set: HashSet<Tile>,
         pending_vec: Vec<Tile>,
-        zoom_offset: u8,
-        insert_empty: bool,
-        format: Format,
-        no_data: Vec<Option<u8>>,
+        processed_set: HashSet<Tile>,
     ) -> Self {
+        let ProcessorConfig {
+            tile_size,
+            padding,
+            min_zoom,
+            max_zoom,
+            continue_file,
+            resume_db_connections,
+            debug,
+            source_priority,
+            megatile_cache,
+            buffer_cache_budget_bytes,
+            mtime_check,
+            debug_megatile,
+            use_mask_band,
+            warp_method,
+            warp_max_error,
+            resampling_algorithm,
+            resampling_algorithm_per_zoom,
+            warp_memory_mb,
+            warp_read_strips,
+            jpeg_quality,
+            webp_quality,
+            avif_quality,
+            avif_speed,
+            jpeg_subsampling,
+            alpha_compression_level,
+            jpeg_restart_interval,
+            zoom_offset,
+            insert_empty,
+            skip_zoom,
+            tile_filter,
+            blend_edges,
+            format,
+            tile_row_order,
+            auto_format_threshold,
+            alpha_storage,
+            input_coefficients,
+            color_table,
+            elevation_encoding,
+            alpha_threshold,
+        } = config;
+
+        let ProcessorChannels {
+            stats_tx,
+            data_tx,
+            progress_tx,
+            limits,
+            interrupted,
+        } = channels;
+
         let total = pending_set.len();
 
-        let state = State::new(pending_vec, pending_set, max_zoom, zoom_offset);
+        let state = State::new(
+            pending_vec,
+            pending_set,
+            processed_set,
+            min_zoom,
+            max_zoom,
+            zoom_offset,
+            tile_filter.clone(),
+        );
 
-        // signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&no_resume)).unwrap();
+        let pools = sources.iter().map(|_| Mutex::new(Vec::new())).collect();
 
-        let pool = Arc::new(Mutex::new(Vec::<Dataset>::new()));
+        let select_pool = continue_file.as_deref().map(|continue_file| {
+            ConnectionPool::open(continue_file, resume_db_connections)
+                .expect("error opening continue mbtiles connections")
+        });
 
-        let select_conn = continue_file.map(|continue_file| {
-            Arc::new(Mutex::new(
-                Connection::open_with_flags(continue_file, OpenFlags::SQLITE_OPEN_READ_ONLY)
-                    .expect("error opening continue mbtiles connection"),
-            ))
+        let verify_checksums = select_pool.as_ref().is_some_and(|select_pool| {
+            has_table(&select_pool.get(), "checksums").expect("error checking checksums table")
         });
 
-        let band_count = ((no_data.len() + 1) / 2) * 2;
+        let png_dictionary = select_pool.as_ref().and_then(|select_pool| {
+            read_dictionary(&select_pool.get()).expect("error reading zstd dictionary")
+        });
+
+        // elevation is encoded to RGBA already in the megatile
+        let band_count = if elevation_encoding.is_some() {
+            4
+        } else {
+            sources[0].no_data.len().div_ceil(2) * 2
+        };
 
         Self {
-            buffer_cache: Arc::new(Mutex::new(HashMap::new())),
+            buffer_cache: MonitoredMutex::new("buffer_cache", LruCache::new_unbounded()),
+            buffer_cache_bytes: AtomicUsize::new(0),
+            buffer_cache_budget_bytes,
+            buffer_cache_over_budget: AtomicBool::new(false),
+            peak_buffer_cache_bytes: AtomicUsize::new(0),
+            peak_megatile_size_bytes: AtomicUsize::new(0),
+            peak_out_buffer_size_bytes: AtomicUsize::new(0),
             tile_size,
+            padding,
+            min_zoom,
             max_zoom,
-            pool,
+            pools,
             counter: AtomicUsize::new(0),
+            warp_counter: AtomicUsize::new(0),
             total,
-            select_conn,
+            select_pool,
+            verify_checksums,
+            png_dictionary,
             stats_tx,
             debug,
-            source_file: source_file.to_path_buf(),
-            state: Arc::new(Mutex::new(state)),
-            transform,
+            sources,
+            source_priority,
+            megatile_cache,
+            mtime_check,
+            source_modified: AtomicBool::new(false),
+            interrupted,
+            failure: Mutex::new(None),
+            debug_megatile,
+            state: MonitoredMutex::new("state", state),
+            progress_tx,
+            use_mask_band,
+            warp_method,
+            warp_max_error,
+            resampling_algorithm,
+            resampling_algorithm_per_zoom,
+            warp_memory_mb,
+            warp_read_strips,
             jpeg_quality,
+            webp_quality,
+            avif_quality,
+            avif_speed,
+            jpeg_subsampling,
+            alpha_compression_level,
+            jpeg_restart_interval,
             limits,
             data_tx,
             zoom_offset,
             insert_empty,
+            skip_zoom,
+            tile_filter,
+            blend_edges,
             format,
+            tile_row_order,
+            auto_format_threshold,
+            alpha_storage,
+            input_coefficients,
+            color_table,
+            elevation_encoding,
             band_count,
+            alpha_threshold,
+        }
+    }
+
+    /// Returns size of the stored tile including the padding.
+    fn padded_tile_size(&self) -> u16 {
+        self.tile_size + 2 * self.padding
+    }
+
+    /// Returns resampling algorithm of the zoom level.
+    fn resampling_algorithm(&self, zoom: u8) -> ResamplingAlgorithm {
+        self.resampling_algorithm_per_zoom
+            .get(&zoom)
+            .copied()
+            .unwrap_or(self.resampling_algorithm)
+    }
+
+    /// Stores the first error of the workers and stops processing the tasks.
+    fn fail(&self, e: TilerError) {
+        let mut failure = self.failure.lock().expect("error locking failure");
+
+        if failure.is_none() {
+            *failure = Some(e);
+        }
+
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns the error which stopped the processing.
+    pub fn take_failure(&self) -> Option<TilerError> {
+        self.failure.lock().expect("error locking failure").take()
+    }
+
+    /// Returns peak sizes of the buffers allocated so far.
+    pub fn peak_memory(&self) -> PeakMemory {
+        PeakMemory {
+            megatile_bytes: self.peak_megatile_size_bytes.load(Ordering::Relaxed),
+            out_buffer_bytes: self.peak_out_buffer_size_bytes.load(Ordering::Relaxed),
+            buffer_cache_bytes: self.peak_buffer_cache_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Inserts pixels of the tile to the buffer cache, tracking its peak size. Evicts tiles if the
+    /// cache exceeds its budget.
+    fn cache_buffer(&self, tile: Tile, rgba: Vec<u8>) {
+        // no parent is composed from the tile
+        if tile.zoom <= self.min_zoom
+            || self.tile_filter.as_ref().is_some_and(|tile_filter| {
+                tile.parent()
+                    .is_none_or(|parent| !tile_filter.matches_or_ancestor(&parent, self.min_zoom))
+            })
+        {
+            return;
+        }
+
+        let size = rgba.len();
+
+        let mut buffer_cache = self.buffer_cache.lock();
+
+        let replaced = buffer_cache
+            .insert(tile, rgba)
+            .map_or(0, |buffer| buffer.len());
+
+        let bytes = self.buffer_cache_bytes.fetch_add(size, Ordering::Relaxed) + size;
+
+        self.buffer_cache_bytes
+            .fetch_sub(replaced, Ordering::Relaxed);
+
+        let mut bytes = bytes - replaced;
+
+        if bytes > self.buffer_cache_budget_bytes {
+            bytes -= self.evict(&mut buffer_cache, bytes - self.buffer_cache_budget_bytes);
+        }
+
+        self.peak_buffer_cache_bytes
+            .fetch_max(bytes, Ordering::Relaxed);
+    }
+
+    /// Evicts the least recently cached tiles until `excess` bytes are freed. Tiles still needed to
+    /// compose their pending parent are kept. Returns number of the freed bytes.
+    fn evict(&self, buffer_cache: &mut LruCache<Tile, Vec<u8>>, excess: usize) -> usize {
+        let evictable: Vec<_> = {
+            let state = self.state.lock();
+
+            buffer_cache
+                .iter()
+                .take(EVICTION_SCAN_LIMIT)
+                .filter(|(tile, _)| {
+                    tile.parent()
+                        .is_none_or(|parent| !state.is_pending(&parent))
+                })
+                .scan(0, |freed, (tile, buffer)| {
+                    (*freed < excess).then(|| {
+                        *freed += buffer.len();
+
+                        *tile
+                    })
+                })
+                .collect()
+        };
+
+        let freed = evictable
+            .iter()
+            .filter_map(|tile| buffer_cache.remove(tile))
+            .map(|buffer| buffer.len())
+            .sum();
+
+        self.buffer_cache_bytes.fetch_sub(freed, Ordering::Relaxed);
+
+        if freed < excess && !self.buffer_cache_over_budget.swap(true, Ordering::Relaxed) {
+            warn!(
+                "Buffer cache exceeds --cache-size-mb but the cached tiles are still needed to compose their parents."
+            );
+        }
+
+        freed
+    }
+
+    /// Removes pixels of the tiles from the buffer cache.
+    fn take_buffers(&self, tiles: &[Tile]) -> Vec<Option<Vec<u8>>> {
+        let mut buffer_cache = self.buffer_cache.lock();
+
+        let buffers: Vec<_> = tiles.iter().map(|tile| buffer_cache.remove(tile)).collect();
+
+        self.buffer_cache_bytes.fetch_sub(
+            buffers.iter().flatten().map(Vec::len).sum(),
+            Ordering::Relaxed,
+        );
+
+        let hits = buffers.iter().flatten().count();
+
+        self.stats_tx
+            .send(StatsMsg::BufferCache {
+                hits,
+                misses: buffers.len() - hits,
+            })
+            .expect("error sending stats");
+
+        buffers
+    }
+
+    /// Returns error or warns (once) if modification time of any source has changed since the start.
+    fn check_source_mtime(&self) -> Result<(), TilerError> {
+        let Some(ref mtime_check) = self.mtime_check else {
+            return Ok(());
+        };
+
+        for (path, mtime) in &mtime_check.mtimes {
+            if fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .is_ok_and(|current| current == *mtime)
+            {
+                continue;
+            }
+
+            match mtime_check.action {
+                MtimeCheckAction::Abort => return Err(TilerError::SourceModified(path.clone())),
+                MtimeCheckAction::Warn => {
+                    if !self.source_modified.swap(true, Ordering::Relaxed) {
+                        warn!("Source file modified during tiling: {}", path.display());
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the megatile of the ancestor tile from the megatile cache if it is up to date.
+    fn read_cached_megatile(&self, ancestor: &Tile, mega_size: u16) -> Option<Vec<u8>> {
+        let cache = self.megatile_cache.as_ref()?;
+
+        let path = cache.dir.join(ancestor.zoom_path("raw"));
+
+        let metadata = fs::metadata(&path).ok()?;
+
+        if metadata.modified().ok()? < cache.source_mtime
+            || metadata.len() != (mega_size as u64) * (mega_size as u64) * self.band_count as u64
+        {
+            return None;
+        }
+
+        fs::read(path).ok()
+    }
+
+    /// Stores the megatile of the ancestor tile to the megatile cache.
+    fn write_cached_megatile(&self, ancestor: &Tile, megatile: &[u8]) {
+        let Some(ref cache) = self.megatile_cache else {
+            return;
+        };
+
+        let path = cache.dir.join(ancestor.zoom_path("raw"));
+
+        let tmp_path = path.with_extension("raw.tmp");
+
+        // readers never see partially written megatile
+        if let Err(e) = fs::create_dir_all(path.parent().expect("megatile path should have parent"))
+            .and_then(|()| fs::write(&tmp_path, megatile))
+            .and_then(|()| fs::rename(&tmp_path, &path))
+        {
+            warn!("Failed to write cached megatile {}: {e}", path.display());
+        }
+    }
+
+    /// Decompresses tile data compressed with `--png-post-compress`.
+    fn post_decompress(&self, data: Vec<u8>) -> Vec<u8> {
+        let Some(ref dictionary) = self.png_dictionary else {
+            return data;
+        };
+
+        let mut decompressed = Vec::new();
+
+        zstd::stream::Decoder::with_dictionary(data.as_slice(), dictionary)
+            .expect("zstd decoder should be created")
+            .read_to_end(&mut decompressed)
+            .expect("error zstd-decoding");
+
+        decompressed
+    }
+
+    /// Opens lock file of the megatile of the ancestor tile in the shared megatile cache.
+    fn open_megatile_lock(&self, ancestor: &Tile) -> Option<RwLock<File>> {
+        let cache = self.megatile_cache.as_ref().filter(|cache| cache.shared)?;
+
+        let path = cache.dir.join(ancestor.zoom_path("raw.lock"));
+
+        fs::create_dir_all(path.parent().expect("megatile path should have parent"))
+            .and_then(|()| {
+                File::options()
+                    .create(true)
+                    .truncate(false)
+                    .write(true)
+                    .open(&path)
+            })
+            .map(RwLock::new)
+            .inspect_err(|e| {
+                warn!("Failed to open megatile lock {}: {e}", path.display());
+            })
+            .ok()
+    }
+
+    /// Returns the megatile of the ancestor tile from the megatile cache or warps (and caches) it.
+    /// With the shared cache the megatile is warped under exclusive lock so that processes sharing
+    /// the cache don't warp it simultaneously. Returns also the step mark for debug output.
+    fn read_or_warp_megatile(
+        &self,
+        ancestor: &Tile,
+        mega_size: u16,
+    ) -> Result<(Vec<u8>, char), TilerError> {
+        if let Some(megatile) = self.read_cached_megatile(ancestor, mega_size) {
+            return Ok((megatile, 'R'));
+        }
+
+        let mut lock = self.open_megatile_lock(ancestor);
+
+        let guard = lock
+            .as_mut()
+            .map(|lock| lock.write().expect("megatile lock should be acquired"));
+
+        // other process could warp the megatile while waiting for the lock
+        if guard.is_some()
+            && let Some(megatile) = self.read_cached_megatile(ancestor, mega_size)
+        {
+            return Ok((megatile, 'R'));
+        }
+
+        let megatiles = (0..self.sources.len())
+            .map(|index| self.warp_megatile(index, ancestor, mega_size))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let megatile = if megatiles.len() == 1 {
+            megatiles.into_iter().next().unwrap()
+        } else {
+            self.composite(megatiles, ancestor, mega_size)
+        };
+
+        self.write_cached_megatile(ancestor, &megatile);
+
+        drop(guard);
+
+        Ok((megatile, 'W'))
+    }
+
+    /// Warps the source of the given index into the megatile of the ancestor tile.
+    fn warp_megatile(
+        &self,
+        index: usize,
+        ancestor: &Tile,
+        mega_size: u16,
+    ) -> Result<Vec<u8>, TilerError> {
+        if let Some(ref mtime_check) = self.mtime_check
+            && self
+                .warp_counter
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(mtime_check.interval)
+        {
+            self.check_source_mtime()?;
+        }
+
+        let ds = self.pools[index]
+            .lock()
+            .expect("error locking dataset pool")
+            .pop();
+
+        let source_ds = ds.unwrap_or_else(|| {
+            let source = &self.sources[index];
+
+            open_source(
+                &source.path,
+                source.overview_level,
+                source.palette_nodata_index,
+            )
+            .expect("Error opening source")
+        });
+
+        let warp_size = mega_size
+            .checked_shr(self.sources[index].overzoom.into())
+            .unwrap_or_default()
+            .max(1);
+
+        let options = WarpOptions {
+            transform: &self.sources[index].transform,
+            use_mask_band: self.use_mask_band,
+            method: self.warp_method,
+            max_error: self.warp_max_error,
+            resampling: self.resampling_algorithm(ancestor.zoom + self.zoom_offset),
+            memory_limit_mb: self.warp_memory_mb,
+            cutline: self.sources[index].cutline.as_deref(),
+        };
+
+        let target_ds = if self.elevation_encoding.is_some() {
+            warp::warp_elevation_tile(
+                &source_ds,
+                ancestor,
+                warp_size - 2 * self.padding,
+                self.padding,
+                &options,
+            )
+        } else {
+            warp::warp_tile(
+                &source_ds,
+                ancestor,
+                warp_size - 2 * self.padding,
+                self.padding,
+                self.band_count,
+                &options,
+            )
+        }
+        .map_err(|code| TilerError::WarpFailed {
+            tile: *ancestor,
+            code,
+        })?;
+
+        self.pools[index]
+            .lock()
+            .expect("error locking dataset pool")
+            .push(source_ds);
+
+        let mut megatile =
+            vec![0u8; ((warp_size as usize) * (warp_size as usize)) * self.band_count];
+
+        // strips are aligned to the tile boundaries
+        let tiles_per_side = ((warp_size / self.tile_size) as usize).max(1);
+
+        let strip_height =
+            tiles_per_side.div_ceil(self.warp_read_strips.into()) * self.tile_size as usize;
+
+        for strip_y in (0..warp_size as usize).step_by(strip_height) {
+            let height = strip_height.min(warp_size as usize - strip_y);
+
+            if let Some(ref elevation_encoding) = self.elevation_encoding {
+                self.assemble_elevation_strip(
+                    &mut megatile,
+                    &target_ds,
+                    elevation_encoding,
+                    strip_y,
+                    height,
+                    warp_size,
+                );
+
+                continue;
+            }
+
+            let buffers: Vec<_> = target_ds
+                .rasterbands()
+                .map(|band| {
+                    band.expect("raster band should be obtained")
+                        .read_as::<u8>(
+                            (0, strip_y as isize),
+                            (warp_size as usize, height),
+                            (warp_size as usize, height),
+                            None,
+                        )
+                        .expect("band should be read")
+                })
+                .collect();
+
+            self.assemble_strip(
+                &mut megatile,
+                &buffers,
+                &self.sources[index].no_data,
+                strip_y,
+                warp_size,
+            );
+        }
+
+        Ok(if warp_size < mega_size {
+            self.upscale_megatile(megatile, warp_size, mega_size)
+        } else {
+            megatile
+        })
+    }
+
+    /// Upscales the megatile warped at the source resolution to the megatile size.
+    fn upscale_megatile(&self, megatile: Vec<u8>, warp_size: u16, mega_size: u16) -> Vec<u8> {
+        // encoded elevation can't be interpolated
+        let filter = match self.resampling_algorithm_per_zoom.get(&self.max_zoom) {
+            Some(algorithm) if self.elevation_encoding.is_none() => algorithm.filter_type(),
+            _ => FilterType::Nearest,
+        };
+
+        let (warp_size, mega_size) = (u32::from(warp_size), u32::from(mega_size));
+
+        if self.band_count == 2 {
+            let image = GrayAlphaImage::from_vec(warp_size, warp_size, megatile)
+                .expect("image should be created");
+
+            image::imageops::resize(&image, mega_size, mega_size, filter).into_raw()
+        } else {
+            let image = RgbaImage::from_vec(warp_size, warp_size, megatile)
+                .expect("image should be created");
+
+            image::imageops::resize(&image, mega_size, mega_size, filter).into_raw()
         }
     }
 
+    /// Reads the strip of warped elevation and alpha starting at the row `strip_y` and writes it
+    /// RGB encoded to the megatile. Nodata pixels are left transparent with RGB `0, 0, 0`.
+    fn assemble_elevation_strip(
+        &self,
+        megatile: &mut [u8],
+        target_ds: &Dataset,
+        elevation_encoding: &ElevationEncoding,
+        strip_y: usize,
+        height: usize,
+        mega_size: u16,
+    ) {
+        let read = |index| {
+            target_ds
+                .rasterband(index)
+                .expect("raster band should be obtained")
+                .read_as::<f32>(
+                    (0, strip_y as isize),
+                    (mega_size as usize, height),
+                    (mega_size as usize, height),
+                    None,
+                )
+                .expect("band should be read")
+        };
+
+        let rgb = elevation_encoding.encode_all(read(1).data());
+
+        let alpha = read(2);
+
+        for (i, (rgb, &alpha)) in rgb.chunks_exact(3).zip(alpha.data()).enumerate() {
+            if alpha <= 0.0 {
+                continue;
+            }
+
+            let offset = (strip_y * mega_size as usize + i) * 4;
+
+            megatile[offset..offset + 3].copy_from_slice(rgb);
+
+            megatile[offset + 3] = 255;
+        }
+    }
+
+    /// Writes pixels of the strip of band buffers starting at the row `strip_y` to the megatile.
+    /// Like in GDAL, a pixel is nodata (left transparent) only if all bands with nodata value equal it.
+    fn assemble_strip(
+        &self,
+        megatile: &mut [u8],
+        buffers: &[Buffer<u8>],
+        no_data: &[Option<u8>],
+        strip_y: usize,
+        mega_size: u16,
+    ) {
+        let height = buffers[0].height();
+
+        let has_no_data = no_data.iter().any(Option::is_some);
+
+        for x in 0..mega_size as usize {
+            for y in 0..height {
+                if has_no_data
+                    && no_data.iter().zip(buffers).all(|(no_data, buffer)| {
+                        no_data.is_none_or(|no_data| buffer[(y, x)] == no_data)
+                    })
+                {
+                    continue;
+                }
+
+                let offset = (x + (strip_y + y) * mega_size as usize) * self.band_count;
+
+                for (i, buffer) in buffers.iter().enumerate() {
+                    let b = buffer[(y, x)];
+
+                    megatile[offset + i] = self
+                        .input_coefficients
+                        .as_ref()
+                        .and_then(|coefficients| coefficients.get(i))
+                        .map_or(b, |&(scale, shift)| {
+                            (f32::from(b) * scale + shift).clamp(0.0, 255.0) as u8
+                        });
+                }
+            }
+        }
+    }
+
+    /// Composites megatiles of all the sources pixel by pixel according to the source priority.
+    fn composite(&self, megatiles: Vec<Vec<u8>>, ancestor: &Tile, mega_size: u16) -> Vec<u8> {
+        let bbox = ancestor.bounds(mega_size - 2 * self.padding);
+
+        let pixel_size = (bbox.max_x - bbox.min_x) / f64::from(mega_size - 2 * self.padding);
+
+        let padding = f64::from(self.padding);
+
+        let alpha = self.band_count - 1;
+
+        let mut result = vec![0u8; megatiles[0].len()];
+
+        let mut weights = vec![0f64; megatiles.len()];
+
+        for x in 0..mega_size as usize {
+            for y in 0..mega_size as usize {
+                let offset = (x + y * mega_size as usize) * self.band_count;
+
+                let pixel = offset..offset + self.band_count;
+
+                // first one wins on a tie
+                let max_alpha = megatiles
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .max_by_key(|(_, megatile)| megatile[offset + alpha])
+                    .map(|(i, _)| i)
+                    .expect("megatiles should not be empty");
+
+                let covered = |i: &usize| megatiles[*i][offset + alpha] > 0;
+
+                let winner = match self.source_priority {
+                    SourcePriority::FirstWins => (0..megatiles.len()).find(covered),
+                    SourcePriority::LastWins => (0..megatiles.len()).rev().find(covered),
+                    SourcePriority::MaxAlpha | SourcePriority::Blend => None,
+                }
+                .unwrap_or(max_alpha);
+
+                if self.source_priority != SourcePriority::Blend {
+                    result[pixel.clone()].copy_from_slice(&megatiles[winner][pixel]);
+
+                    continue;
+                }
+
+                let px = bbox.min_x + (x as f64 - padding + 0.5) * pixel_size;
+                let py = bbox.max_y - (y as f64 - padding + 0.5) * pixel_size;
+
+                for (i, megatile) in megatiles.iter().enumerate() {
+                    let bounds = self.sources[i].bounds;
+
+                    let edge_distance = (px - bounds[0])
+                        .min(bounds[2] - px)
+                        .min(py - bounds[1])
+                        .min(bounds[3] - py)
+                        .max(0.0);
+
+                    weights[i] = f64::from(megatile[offset + alpha]) * edge_distance;
+                }
+
+                let weight_sum: f64 = weights.iter().sum();
+
+                if weight_sum == 0.0 {
+                    result[pixel.clone()].copy_from_slice(&megatiles[max_alpha][pixel]);
+
+                    continue;
+                }
+
+                for band in 0..alpha {
+                    let value: f64 = megatiles
+                        .iter()
+                        .zip(&weights)
+                        .map(|(megatile, weight)| f64::from(megatile[offset + band]) * weight)
+                        .sum();
+
+                    result[offset + band] = (value / weight_sum).round() as u8;
+                }
+
+                result[offset + alpha] = megatiles[max_alpha][offset + alpha];
+            }
+        }
+
+        result
+    }
+
     pub fn process_task(&self, task: Vec<Tile>, worker: &Worker<Vec<Tile>>) {
         let mut megatile: Option<Vec<u8>> = None;
 
         let mut todo = task.len();
 
         for tile in task {
+            // the rest of the task stays pending in the state
+            if self.interrupted.load(Ordering::Relaxed) {
+                return;
+            }
+
             let counter = self.counter.fetch_add(1, Ordering::Relaxed);
 
             let top_instant = Instant::now();
 
             self.stats_tx
                 .send(StatsMsg::Stats(
-                    counter as f32 / self.total as f32 * 100.0,
-                    self.buffer_cache
-                        .lock()
-                        .expect("error locking buffer_cache")
-                        .len(),
+                    counter,
+                    self.total,
+                    self.buffer_cache.lock().len(),
                     tile,
                 ))
                 .expect("error sending stats");
 
             let mut steps = Vec::new();
 
+            let mut stored = false;
+
             'out: {
                 'resume: {
-                    if let Some(ref select_conn) = self.select_conn {
+                    if let Some(ref select_pool) = self.select_pool {
                         let (rgb, alpha) = {
                             let select_instant = Instant::now();
 
-                            let conn = select_conn.lock().expect("error locking select_conn");
+                            let conn = select_pool.get();
 
                             let mut stmt = conn
-                                .prepare("SELECT tile_data, tile_alpha FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3")
+                                .prepare(&format!(
+                                    "SELECT tile_data, {}{} FROM tiles{} WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+                                    match self.format {
+                                        Format::JPEG | Format::Auto => "tile_alpha",
+                                        Format::PNG
+                                        | Format::PNG8
+                                        | Format::WebP
+                                        | Format::AVIF
+                                        | Format::TerrainRgb
+                                        | Format::Terrarium => "x''",
+                                    },
+                                    if self.verify_checksums { ", sha256_hex" } else { "" },
+                                    if self.verify_checksums {
+                                        " LEFT JOIN checksums USING (zoom_level, tile_column, tile_row)"
+                                    } else {
+                                        ""
+                                    }
+                                ))
                                 .expect("select statement should be prepared");
 
                             let mut rows = stmt
-                                .query((tile.zoom, tile.x, tile.reversed_y()))
+                                .query((tile.zoom, tile.x, self.tile_row_order.row(&tile)))
                                 .expect("tile should be queried");
 
                             let Some(row) = rows.next().expect("error getting selected tile")
@@ -160,6 +1057,21 @@ impl Processor {
                                 .get::<_, Vec<u8>>(1)
                                 .expect("error getting selected alpha");
 
+                            if self.verify_checksums
+                                && row
+                                    .get::<_, Option<String>>(2)
+                                    .expect("error getting selected checksum")
+                                    .is_some_and(|checksum| checksum != tile_checksum(&rgb, &alpha))
+                            {
+                                self.stats_tx
+                                    .send(StatsMsg::ChecksumMismatch)
+                                    .expect("error sending stats");
+
+                                steps.push('✗');
+
+                                break 'resume;
+                            }
+
                             self.stats_tx
                                 .send(StatsMsg::Duration(
                                     Metric::Select,
@@ -171,16 +1083,7 @@ impl Processor {
                         };
 
                         if tile.zoom < self.max_zoom {
-                            let children = tile.children();
-
-                            let mut buffer_cache = self
-                                .buffer_cache
-                                .lock()
-                                .expect("error locking buffer_cache");
-
-                            for tile in children {
-                                buffer_cache.remove(&tile);
-                            }
+                            self.take_buffers(&tile.children());
                         }
 
                         if rgb.is_empty() {
@@ -191,34 +1094,84 @@ impl Processor {
 
                         steps.push('●');
 
-                        let cursor = Cursor::new(&rgb);
+                        let format = match self.format {
+                            Format::Auto if is_jpeg(&rgb) => Format::JPEG,
+                            Format::Auto => Format::PNG,
+                            format => format,
+                        };
 
-                        let decoder =
-                            JpegDecoder::new(cursor).expect("error creading jpeg decoder");
+                        let rgba = match format {
+                            Format::JPEG => {
+                                let cursor = Cursor::new(&rgb);
 
-                        let mut tile_data = vec![0; decoder.total_bytes() as usize];
+                                let decoder =
+                                    JpegDecoder::new(cursor).expect("error creading jpeg decoder");
 
-                        decoder
-                            .read_image(&mut tile_data)
-                            .expect("error image-decoding");
+                                let mut tile_data = vec![0; decoder.total_bytes() as usize];
 
-                        let alpha = if alpha.is_empty() {
-                            vec![255; 256 * 256]
-                        } else {
-                            zstd::stream::decode_all(alpha.as_slice()).expect("error zstd-decoding")
-                        };
+                                decoder
+                                    .read_image(&mut tile_data)
+                                    .expect("error image-decoding");
 
-                        let rgba = tile_data
-                            .chunks(3)
-                            .zip(alpha.chunks(1))
-                            .flat_map(|(a, b)| a.iter().chain(b))
-                            .copied()
-                            .collect::<Vec<u8>>();
+                                let alpha = if alpha.is_empty() {
+                                    let size = self.padded_tile_size() as usize;
+
+                                    vec![255; size * size]
+                                } else {
+                                    zstd::stream::decode_all(alpha.as_slice())
+                                        .expect("error zstd-decoding")
+                                };
+
+                                tile_data
+                                    .chunks(3)
+                                    .zip(alpha.chunks(1))
+                                    .flat_map(|(a, b)| a.iter().chain(b))
+                                    .copied()
+                                    .collect::<Vec<u8>>()
+                            }
+                            Format::AVIF => {
+                                unreachable!("continuing is not supported with AVIF")
+                            }
+                            Format::WebP => {
+                                webp_tile::decode(&self.post_decompress(rgb), self.band_count)
+                                    .expect("error WebP-decoding")
+                            }
+                            Format::TerrainRgb | Format::Terrarium => {
+                                let mut rgba = image::load_from_memory_with_format(
+                                    &self.post_decompress(rgb),
+                                    image::ImageFormat::Png,
+                                )
+                                .expect("error image-decoding")
+                                .into_rgba8()
+                                .into_raw();
+
+                                // nodata is stored as black
+                                for pixel in rgba.chunks_exact_mut(4) {
+                                    if pixel[0..3] == [0, 0, 0] {
+                                        pixel[3] = 0;
+                                    }
+                                }
+
+                                rgba
+                            }
+                            Format::PNG | Format::PNG8 | Format::Auto => {
+                                let png = self.post_decompress(rgb);
+
+                                let image = image::load_from_memory_with_format(
+                                    &png,
+                                    image::ImageFormat::Png,
+                                )
+                                .expect("error image-decoding");
+
+                                if self.band_count == 2 {
+                                    image.into_luma_alpha8().into_raw()
+                                } else {
+                                    image.into_rgba8().into_raw()
+                                }
+                            }
+                        };
 
-                        self.buffer_cache
-                            .lock()
-                            .expect("error locking buffer_cache")
-                            .insert(tile, rgba);
+                        self.cache_buffer(tile, rgba);
 
                         break 'out;
                     }
@@ -227,83 +1180,124 @@ impl Processor {
                 let rgba = if tile.zoom < self.max_zoom {
                     steps.push('C');
 
-                    let mut out_buffer =
-                        vec![
-                            0u8;
-                            self.tile_size as usize * self.tile_size as usize * self.band_count * 4
-                        ];
+                    let size = self.padded_tile_size() as usize;
+
+                    let tile_size = self.tile_size as usize;
+
+                    let padding = self.padding as usize;
+
+                    let mut out_buffer = vec![0u8; size * size * self.band_count * 4];
+
+                    self.peak_out_buffer_size_bytes
+                        .fetch_max(out_buffer.len(), Ordering::Relaxed);
 
                     let mut has_data = false;
 
                     let children = tile.children();
 
-                    let sectors: Vec<_> = {
-                        let mut buffer_cache = self
-                            .buffer_cache
-                            .lock()
-                            .expect("error locking buffer_cache");
-
-                        children
-                            .iter()
-                            .map(|tile| buffer_cache.remove(tile))
-                            .collect()
-                    };
+                    let sectors = self.take_buffers(&children);
 
                     let compose_instant = Instant::now();
 
-                    for (i, sector) in sectors.into_iter().enumerate() {
-                        let Some(sector) = sector else {
-                            continue;
-                        };
+                    // padding of a child overlaps its siblings, so their interiors are copied over it
+                    let passes = if padding > 0 { 2 } else { 1 };
 
-                        has_data = true;
+                    for pass in 0..passes {
+                        for (i, sector) in sectors.iter().enumerate() {
+                            let Some(sector) = sector else {
+                                continue;
+                            };
 
-                        let so_x = (i & 1) * self.tile_size as usize;
-                        let so_y = (i >> 1) * self.tile_size as usize;
+                            has_data = true;
 
-                        for x in 0..self.tile_size as usize {
-                            for y in 0..self.tile_size as usize {
-                                let offset1 = ((x + so_x)
-                                    + (y + so_y) * self.tile_size as usize * 2)
-                                    * self.band_count;
+                            let so_x = padding + (i & 1) * tile_size;
+                            let so_y = padding + (i >> 1) * tile_size;
+
+                            let range = if pass == 0 {
+                                0..size
+                            } else {
+                                padding..padding + tile_size
+                            };
+
+                            for x in range.clone() {
+                                for y in range.clone() {
+                                    let offset1 =
+                                        ((x + so_x) + (y + so_y) * size * 2) * self.band_count;
+
+                                    let offset2 = (x + y * size) * self.band_count;
+
+                                    out_buffer[offset1..(self.band_count + offset1)]
+                                        .copy_from_slice(
+                                            &sector[offset2..(self.band_count + offset2)],
+                                        );
+                                }
+                            }
+                        }
+                    }
 
-                                let offset2 = (x + y * self.tile_size as usize) * self.band_count;
+                    // the outer padding is not covered by the children, replicate their edges
+                    if has_data && padding > 0 {
+                        let side = size * 2;
 
-                                out_buffer[offset1..(self.band_count + offset1)]
-                                    .copy_from_slice(&sector[offset2..(self.band_count + offset2)]);
+                        for x in 0..side {
+                            for y in 0..side {
+                                if (padding..side - padding).contains(&x)
+                                    && (padding..side - padding).contains(&y)
+                                {
+                                    continue;
+                                }
+
+                                let offset1 = (x + y * side) * self.band_count;
+
+                                let offset2 = (x.clamp(padding, side - padding - 1)
+                                    + y.clamp(padding, side - padding - 1) * side)
+                                    * self.band_count;
+
+                                out_buffer
+                                    .copy_within(offset2..(self.band_count + offset2), offset1);
                             }
                         }
                     }
 
-                    if has_data {
+                    if has_data && let Some(ref elevation_encoding) = self.elevation_encoding {
+                        Some(elevation_encoding.downsample(&out_buffer, size))
+                    } else if has_data {
+                        if self.blend_edges > 0 {
+                            // the children interiors start after the doubled padding
+                            blend::blend_edges(
+                                &mut out_buffer,
+                                size * 2,
+                                2 * padding + tile_size,
+                                self.band_count,
+                                self.blend_edges.into(),
+                            );
+                        }
+
                         let img = if self.band_count == 2 {
                             let image = GrayAlphaImage::from_vec(
-                                u32::from(self.tile_size) * 2,
-                                u32::from(self.tile_size) * 2,
+                                size as u32 * 2,
+                                size as u32 * 2,
                                 out_buffer,
                             )
                             .expect("rgba image should be created");
 
                             image::imageops::resize(
                                 &image,
-                                u32::from(self.tile_size),
-                                u32::from(self.tile_size),
-                                FilterType::Lanczos3,
+                                size as u32,
+                                size as u32,
+                                self.resampling_algorithm(tile.zoom).filter_type(),
                             )
                             .into_raw()
                         } else {
-                            let image = RgbaImage::from_vec(
-                                u32::from(self.tile_size) * 2,
-                                u32::from(self.tile_size) * 2,
-                                out_buffer,
-                            )
-                            .expect("rgba image should be created");
+                            let image =
+                                RgbaImage::from_vec(size as u32 * 2, size as u32 * 2, out_buffer)
+                                    .expect("rgba image should be created");
 
                             image::imageops::resize(
                                 &image,
-                                u32::from(self.tile_size),
-                                u32::from(self.tile_size),
-                                FilterType::Lanczos3,
+                                size as u32,
+                                size as u32,
+                                self.resampling_algorithm(tile.zoom).filter_type(),
                             )
                             .into_raw()
                         };
@@ -315,127 +1309,61 @@ impl Processor {
                             ))
                             .expect("error sending stats");
 
-                        Some(img)
+                        // resampling may leave only faint pixels of the children
+                        let has_opaque = self.alpha_threshold == 1
+                            || img
+                                .chunks_exact(self.band_count)
+                                .any(|pixel| pixel[self.band_count - 1] >= self.alpha_threshold);
+
+                        has_opaque.then_some(img)
                     } else {
                         None
                     }
                 } else
                 // tile.zoom == max_zoom
                 {
-                    let mega_size = self.tile_size << self.zoom_offset;
+                    // the megatile includes the padding on every side
+                    let mega_size = (self.tile_size << self.zoom_offset) + 2 * self.padding;
 
                     let megatile = if let Some(ref megatile) = megatile {
                         megatile
                     } else {
-                        let ds = self.pool.lock().expect("error locking dataset pool").pop();
-
-                        let source_ds = ds.map_or_else(
-                            || Dataset::open(&self.source_file).expect("Error opening source"),
-                            |ds| ds,
-                        );
-
                         let warp_instant = Instant::now();
 
-                        let bbox = tile
+                        let ancestor = tile
                             .ancestor(self.zoom_offset)
-                            .expect("shold have tile ancestor")
-                            .bounds(mega_size);
-
-                        let mut target_ds = DriverManager::get_driver_by_name("MEM")
-                            .expect("MEM driver should be obtained")
-                            .create(
-                                "",
-                                (self.tile_size as usize) << self.zoom_offset,
-                                (self.tile_size as usize) << self.zoom_offset,
-                                self.band_count,
-                            )
-                            .expect("target dataset should be created");
+                            .expect("shold have tile ancestor");
 
-                        let colors = if self.band_count == 2 {
-                            vec![
-                                ColorInterpretation::GrayIndex,
-                                ColorInterpretation::AlphaBand,
-                            ]
-                        } else {
-                            vec![
-                                ColorInterpretation::RedBand,
-                                ColorInterpretation::GreenBand,
-                                ColorInterpretation::BlueBand,
-                                ColorInterpretation::AlphaBand,
-                            ]
-                        };
+                        let (mut megatile1, step) =
+                            match self.read_or_warp_megatile(&ancestor, mega_size) {
+                                Ok(result) => result,
+                                Err(e) => {
+                                    // the rest of the task stays pending in the state
+                                    self.fail(e);
 
-                        for (i, color) in colors.into_iter().enumerate() {
-                            target_ds
-                                .rasterband(i + 1)
-                                .unwrap()
-                                .set_color_interpretation(color)
-                                .unwrap();
-                        }
+                                    return;
+                                }
+                            };
 
-                        target_ds
-                            .set_geo_transform(&[
-                                bbox.min_x,                                          // Top-left x
-                                (bbox.max_x - bbox.min_x) / f64::from(mega_size),    // Pixel width
-                                0.0,        // Rotation (x-axis)
-                                bbox.max_y, // Top-left y
-                                0.0,        // Rotation (y-axis)
-                                -((bbox.max_y - bbox.min_y) / f64::from(mega_size)), // Pixel height (negative for top-down)
-                            ])
-                            .expect("error setting geo transform");
-
-                        steps.push('W');
-
-                        warp::warp(&source_ds, &target_ds, mega_size, &self.transform);
-
-                        let buffers: Vec<_> = target_ds
-                            .rasterbands()
-                            .map(|band| {
-                                band.expect("raster band should be obtained")
-                                    .read_as::<u8>(
-                                        (0, 0),
-                                        (mega_size as usize, mega_size as usize),
-                                        (mega_size as usize, mega_size as usize),
-                                        None,
-                                    )
-                                    .expect("band should be read")
-                            })
-                            .collect();
-
-                        let no_data: Vec<_> = target_ds
-                            .rasterbands()
-                            .map(|band| band.unwrap().no_data_value().map(|nd| nd as u8))
-                            .collect();
-
-                        self.pool
-                            .lock()
-                            .expect("error locking dataset pool")
-                            .push(source_ds);
-
-                        let mut megatile1 = vec![
-                            0u8;
-                            ((mega_size as usize) * (mega_size as usize))
-                                * self.band_count
-                        ];
-
-                        for x in 0..mega_size as usize {
-                            for y in 0..mega_size as usize {
-                                let offset = (x + y * mega_size as usize) * self.band_count;
-
-                                for (i, buffer) in buffers.iter().enumerate() {
-                                    let b = buffer[(y, x)];
-
-                                    if no_data[i].map_or(false, |v| b == v) {
-                                        for j in 0..buffers.len() {
-                                            megatile1[offset + j] = 0;
-                                        }
-
-                                        break;
-                                    }
+                        steps.push(step);
 
-                                    megatile1[offset + i] = b;
-                                }
-                            }
+                        self.peak_megatile_size_bytes
+                            .fetch_max(megatile1.len(), Ordering::Relaxed);
+
+                        if let Some(ref color_table) = self.color_table {
+                            color::apply(&mut megatile1, self.band_count, color_table);
+                        }
+
+                        if let Some(ref debug_megatile) = self.debug_megatile
+                            && debug_megatile.tile == ancestor
+                            && let Err(e) = debug_megatile.write(
+                                &megatile1,
+                                mega_size,
+                                self.padding,
+                                self.band_count,
+                            )
+                        {
+                            warn!("Failed to write debug megatile {ancestor}: {e}");
                         }
 
                         self.stats_tx
@@ -452,26 +1380,27 @@ impl Processor {
 
                     let (sx, sy) = tile.sector_in_ancestor(self.zoom_offset);
 
-                    let mut out_buffer =
-                        vec![
-                            0u8;
-                            self.tile_size as usize * self.tile_size as usize * self.band_count
-                        ];
+                    let size = self.padded_tile_size() as usize;
+
+                    let mut out_buffer = vec![0u8; size * size * self.band_count];
+
+                    self.peak_out_buffer_size_bytes
+                        .fetch_max(out_buffer.len(), Ordering::Relaxed);
 
                     let mut is_empty = true;
 
-                    for x in 0..self.tile_size as usize {
-                        for y in 0..self.tile_size as usize {
+                    for x in 0..size {
+                        for y in 0..size {
                             let in_offset = (x
                                 + (sx as usize) * (self.tile_size as usize)
                                 + (y + (sy as usize) * (self.tile_size as usize))
                                     * (mega_size as usize))
                                 * self.band_count;
 
-                            let out_offset = (x + y * self.tile_size as usize) * self.band_count;
+                            let out_offset = (x + y * size) * self.band_count;
 
                             // TODO alternative - mask
-                            if megatile[in_offset + self.band_count - 1] > 0 {
+                            if megatile[in_offset + self.band_count - 1] >= self.alpha_threshold {
                                 is_empty = false;
 
                                 for i in 0..self.band_count {
@@ -490,12 +1419,48 @@ impl Processor {
                     if is_empty { None } else { Some(out_buffer) }
                 }; // tile.zoom < max_zoom
 
-                if let Some(rgba) = rgba {
+                if self.skip_zoom.contains(&tile.zoom)
+                    || self
+                        .tile_filter
+                        .as_ref()
+                        .is_some_and(|tile_filter| !tile_filter.matches(&tile))
+                {
+                    steps.push('S');
+
+                    // keep for composing the parent
+                    if let Some(rgba) = rgba {
+                        self.cache_buffer(tile, rgba);
+                    }
+                } else if let Some(rgba) = rgba {
                     steps.push('●');
 
                     let mut encoded = Vec::new();
 
-                    let alpha_enc = match self.format {
+                    let format = match self.format {
+                        Format::Auto => {
+                            let opaque = rgba
+                                .chunks_exact(self.band_count)
+                                .filter(|chunk| chunk[self.band_count - 1] == 255)
+                                .count();
+
+                            let format = if opaque as f32
+                                > self.auto_format_threshold * (rgba.len() / self.band_count) as f32
+                            {
+                                Format::JPEG
+                            } else {
+                                Format::PNG
+                            };
+
+                            self.stats_tx
+                                .send(StatsMsg::AutoFormat(format))
+                                .expect("error sending stats");
+
+                            format
+                        }
+                        format => format,
+                    };
+
+                    let alpha_enc = match format {
                         Format::JPEG => {
                             let mut rgb =
                                 Vec::with_capacity(rgba.len() - rgba.len() / self.band_count);
@@ -514,9 +1479,12 @@ impl Processor {
 
                             let mut alpha_enc = Vec::new();
 
-                            if !fully_opaque {
-                                let mut encoder = zstd::Encoder::new(&mut alpha_enc, 0)
-                                    .expect("zstd encoder should be created");
+                            if !fully_opaque && self.alpha_storage == AlphaStorage::PerTile {
+                                let mut encoder = zstd::Encoder::new(
+                                    &mut alpha_enc,
+                                    self.alpha_compression_level,
+                                )
+                                .expect("zstd encoder should be created");
 
                                 encoder
                                     .write_all(&alpha)
@@ -525,11 +1493,20 @@ impl Processor {
                                 encoder.finish().expect("zstd encoding should be finished");
                             }
 
-                            jpeg_encoder::Encoder::new(&mut encoded, self.jpeg_quality)
+                            let mut encoder =
+                                jpeg_encoder::Encoder::new(&mut encoded, self.jpeg_quality);
+
+                            encoder.set_restart_interval(self.jpeg_restart_interval);
+
+                            if let Some(jpeg_subsampling) = self.jpeg_subsampling {
+                                encoder.set_sampling_factor(jpeg_subsampling.into());
+                            }
+
+                            encoder
                                 .encode(
                                     &rgb,
-                                    self.tile_size,
-                                    self.tile_size,
+                                    self.padded_tile_size(),
+                                    self.padded_tile_size(),
                                     if self.band_count == 2 {
                                         jpeg_encoder::ColorType::Luma
                                     } else {
@@ -540,31 +1517,119 @@ impl Processor {
 
                             alpha_enc
                         }
-                        Format::PNG => {
+                        Format::PNG | Format::Auto => {
+                            let opaque;
+
+                            let (data, color_type) = match self.alpha_storage {
+                                AlphaStorage::PerTile => (
+                                    &rgba,
+                                    if self.band_count == 2 {
+                                        image::ExtendedColorType::La8
+                                    } else {
+                                        image::ExtendedColorType::Rgba8
+                                    },
+                                ),
+                                AlphaStorage::None => {
+                                    opaque = rgba
+                                        .chunks_exact(self.band_count)
+                                        .flat_map(|chunk| &chunk[0..self.band_count - 1])
+                                        .copied()
+                                        .collect::<Vec<u8>>();
+
+                                    (
+                                        &opaque,
+                                        if self.band_count == 2 {
+                                            image::ExtendedColorType::L8
+                                        } else {
+                                            image::ExtendedColorType::Rgb8
+                                        },
+                                    )
+                                }
+                            };
+
                             PngEncoder::new_with_quality(
                                 &mut encoded,
                                 image::codecs::png::CompressionType::Best,
                                 image::codecs::png::FilterType::Adaptive,
                             )
                             .write_image(
+                                data,
+                                u32::from(self.padded_tile_size()),
+                                u32::from(self.padded_tile_size()),
+                                color_type,
+                            )
+                            .expect("PNG should be encoded");
+
+                            vec![]
+                        }
+                        Format::WebP => {
+                            encoded = webp_tile::encode(
                                 &rgba,
-                                self.tile_size as u32,
-                                self.tile_size as u32,
-                                if self.band_count == 2 {
-                                    image::ExtendedColorType::La8
-                                } else {
-                                    image::ExtendedColorType::Rgba8
-                                },
+                                self.band_count,
+                                self.padded_tile_size(),
+                                self.webp_quality,
+                                self.alpha_storage == AlphaStorage::PerTile,
+                            );
+
+                            vec![]
+                        }
+                        Format::TerrainRgb | Format::Terrarium => {
+                            let rgb: Vec<_> = rgba
+                                .chunks_exact(4)
+                                .flat_map(|pixel| &pixel[0..3])
+                                .copied()
+                                .collect();
+
+                            PngEncoder::new_with_quality(
+                                &mut encoded,
+                                image::codecs::png::CompressionType::Best,
+                                image::codecs::png::FilterType::Adaptive,
+                            )
+                            .write_image(
+                                &rgb,
+                                u32::from(self.padded_tile_size()),
+                                u32::from(self.padded_tile_size()),
+                                image::ExtendedColorType::Rgb8,
                             )
                             .expect("PNG should be encoded");
 
+                            vec![]
+                        }
+                        Format::AVIF => {
+                            encoded = avif_tile::encode(
+                                &rgba,
+                                self.band_count,
+                                self.padded_tile_size(),
+                                self.avif_quality,
+                                self.avif_speed,
+                                self.alpha_storage == AlphaStorage::PerTile,
+                            )
+                            .expect("AVIF should be encoded");
+
+                            vec![]
+                        }
+                        Format::PNG8 => {
+                            let quantized = quantize::encode_png8(
+                                &rgba,
+                                self.band_count,
+                                self.padded_tile_size(),
+                                self.alpha_storage == AlphaStorage::PerTile,
+                            )
+                            .expect("PNG should be encoded");
+
+                            encoded = quantized.png;
+
+                            self.stats_tx
+                                .send(StatsMsg::Quantization(quantized.psnr))
+                                .expect("error sending stats");
+
                             vec![]
                         }
                     };
 
                     // println!("Inserting {tile}");
 
-                    let y = tile.reversed_y();
+                    let y = self.tile_row_order.row(&tile);
 
                     self.limits
                         .lock()
@@ -587,10 +1652,9 @@ impl Processor {
                         .send((tile, encoded, alpha_enc))
                         .expect("data shouuld be sent");
 
-                    self.buffer_cache
-                        .lock()
-                        .expect("buffer_cache should be locked")
-                        .insert(tile, rgba);
+                    stored = true;
+
+                    self.cache_buffer(tile, rgba);
                 } else if self.insert_empty {
                     steps.push('○');
 
@@ -598,27 +1662,55 @@ impl Processor {
                     self.data_tx
                         .send((tile, vec![], vec![]))
                         .expect("data shouuld be sent");
+
+                    stored = true;
                 }
             }; // 'out
 
-            let mut status = self.state.lock().expect("state should be locked");
+            // the stored tiles are recorded by the inserter once committed
+            if !stored && let Some(ref progress_tx) = self.progress_tx {
+                progress_tx
+                    .send(vec![tile])
+                    .expect("error sending progress");
+            }
+
+            let mut status = self.state.lock();
 
             todo -= 1;
 
             status.processed(tile);
 
-            if todo == 0 {
-                if let Some(tiles) = status.next() {
-                    worker.push(tiles);
-                }
+            if self.debug && counter.is_multiple_of(ZOOM_PROGRESS_INTERVAL) {
+                self.stats_tx
+                    .send(StatsMsg::ZoomProgress(status.tiles_by_zoom()))
+                    .expect("error sending stats");
+            }
+
+            if todo == 0
+                && let Some(tiles) = status.next_task()
+            {
+                worker.push(tiles);
             }
 
             drop(status);
 
+            if counter.is_multiple_of(CONTENTION_INTERVAL) {
+                self.stats_tx
+                    .send(StatsMsg::Contention {
+                        buffer_cache: self.buffer_cache.contention(),
+                        state: self.state.contention(),
+                    })
+                    .expect("error sending stats");
+            }
+
             if self.debug {
-                print!("|{}", steps.iter().collect::<String>());
+                debug!(%tile, steps = %steps.iter().collect::<String>(), "Processed");
             }
 
+            self.stats_tx
+                .send(StatsMsg::TileComplete(1))
+                .expect("error sending stats");
+
             self.stats_tx
                 .send(StatsMsg::Duration(
                     Metric::Encode,
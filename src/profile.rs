@@ -0,0 +1,98 @@
+use crate::args::{Args, Format, JpegSubsampling};
+use clap::{ArgMatches, parser::ValueSource};
+use serde::Deserialize;
+use std::{collections::HashMap, env, fs, path::PathBuf};
+
+/// Named preset of quality related arguments
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub format: Option<Format>,
+    pub jpeg_quality: Option<u8>,
+    pub jpeg_subsampling: Option<JpegSubsampling>,
+    pub alpha_compression_level: Option<i32>,
+}
+
+fn builtin_profile(name: &str) -> Option<Profile> {
+    Some(match name {
+        "web" => Profile {
+            format: Some(Format::JPEG),
+            jpeg_quality: Some(80),
+            jpeg_subsampling: Some(JpegSubsampling::R420),
+            alpha_compression_level: Some(1),
+        },
+        "print" => Profile {
+            format: Some(Format::PNG),
+            ..Profile::default()
+        },
+        "preview" => Profile {
+            format: Some(Format::JPEG),
+            jpeg_quality: Some(60),
+            jpeg_subsampling: Some(JpegSubsampling::R420),
+            alpha_compression_level: Some(1),
+        },
+        "archive" => Profile {
+            format: Some(Format::JPEG),
+            jpeg_quality: Some(95),
+            jpeg_subsampling: Some(JpegSubsampling::R444),
+            alpha_compression_level: Some(19),
+        },
+        _ => return None,
+    })
+}
+
+/// Returns path of the custom profiles file `$XDG_CONFIG_HOME/freemap-tiler/profiles.toml`.
+fn profiles_file() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|config| config.join("freemap-tiler").join("profiles.toml"))
+}
+
+/// Loads the profile of the name, custom profiles take precedence over the built-in ones.
+pub fn load_profile(name: &str) -> Result<Profile, String> {
+    if let Some(file) = profiles_file().filter(|file| file.exists()) {
+        let content = fs::read_to_string(&file)
+            .map_err(|e| format!("Failed to read {}: {e}", file.display()))?;
+
+        let mut profiles: HashMap<String, Profile> = toml::from_str(&content)
+            .map_err(|e| format!("Failed to parse {}: {e}", file.display()))?;
+
+        if let Some(profile) = profiles.remove(name) {
+            return Ok(profile);
+        }
+    }
+
+    builtin_profile(name).ok_or_else(|| format!("Unknown format profile \"{name}\""))
+}
+
+impl Profile {
+    /// Sets the arguments of the profile which were not explicitly specified on the command line.
+    pub fn apply(self, args: &mut Args, matches: &ArgMatches) {
+        let unset = |id| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if let Some(format) = self.format
+            && unset("format")
+        {
+            args.format = format;
+        }
+
+        if let Some(jpeg_quality) = self.jpeg_quality
+            && unset("jpeg_quality")
+        {
+            args.jpeg_quality = jpeg_quality;
+        }
+
+        if let Some(jpeg_subsampling) = self.jpeg_subsampling
+            && unset("jpeg_subsampling")
+        {
+            args.jpeg_subsampling = Some(jpeg_subsampling);
+        }
+
+        if let Some(alpha_compression_level) = self.alpha_compression_level
+            && unset("alpha_compression_level")
+        {
+            args.alpha_compression_level = alpha_compression_level;
+        }
+    }
+}
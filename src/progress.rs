@@ -0,0 +1,122 @@
+use crate::error::TilerError;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+use tilemath::Tile;
+use tracing::warn;
+
+/// Serde of tiles as `zoom/x/y` strings
+mod tiles_serde {
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+    use std::collections::HashSet;
+    use tilemath::Tile;
+
+    pub fn serialize<S: Serializer>(
+        tiles: &HashSet<Tile>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(tiles.iter().map(ToString::to_string))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<HashSet<Tile>, D::Error> {
+        Vec::<String>::deserialize(deserializer)?
+            .iter()
+            .map(|tile| tile.parse().map_err(D::Error::custom))
+            .collect()
+    }
+}
+
+/// Content of the progress file, the other fields written by the previous versions are ignored
+#[derive(Serialize, Deserialize)]
+struct Progress {
+    #[serde(with = "tiles_serde")]
+    processed_set: HashSet<Tile>,
+}
+
+impl Progress {
+    /// Writes the progress to the file as ZSTD compressed JSON, atomically through a temporary file.
+    fn save_to_file(&self, path: &Path) -> Result<(), TilerError> {
+        let json = serde_json::to_vec(self).expect("progress should be serialized");
+
+        let tmp_path = path.with_extension("tmp");
+
+        fs::write(&tmp_path, zstd::encode_all(json.as_slice(), 0)?)?;
+
+        fs::rename(&tmp_path, path)?;
+
+        Ok(())
+    }
+
+    /// Reads the progress written by `save_to_file`, also uncompressed JSON of the previous versions.
+    fn load_from_file(path: &Path) -> Result<Self, TilerError> {
+        let data = fs::read(path)?;
+
+        let json = zstd::decode_all(data.as_slice()).unwrap_or(data);
+
+        serde_json::from_slice(&json).map_err(|e| {
+            TilerError::InvalidInput(format!("invalid progress file {}: {e}", path.display()))
+        })
+    }
+}
+
+/// Starts the thread collecting the received processed tiles to `processed_set` and writing them
+/// to the progress file every `interval` and after the last sender is dropped.
+pub fn start_writer(
+    path: PathBuf,
+    interval: Duration,
+    processed_set: HashSet<Tile>,
+) -> (Sender<Vec<Tile>>, JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel::<Vec<Tile>>();
+
+    let thread = thread::spawn(move || {
+        let mut progress = Progress { processed_set };
+
+        let mut last_write = Instant::now();
+
+        let mut changed = false;
+
+        loop {
+            let disconnected = match rx.recv_timeout(interval.saturating_sub(last_write.elapsed()))
+            {
+                Ok(tiles) => {
+                    progress.processed_set.extend(tiles);
+
+                    changed = true;
+
+                    false
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => true,
+            };
+
+            if changed && (disconnected || last_write.elapsed() >= interval) {
+                if let Err(e) = progress.save_to_file(&path) {
+                    warn!("Error writing progress file: {e}");
+                }
+
+                last_write = Instant::now();
+
+                changed = false;
+            }
+
+            if disconnected {
+                break;
+            }
+        }
+    });
+
+    (tx, thread)
+}
+
+/// Reads tiles processed by the previous run from the progress file.
+pub fn read_processed(path: &Path) -> Result<HashSet<Tile>, TilerError> {
+    Ok(Progress::load_from_file(path)?.processed_set)
+}
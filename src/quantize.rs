@@ -0,0 +1,99 @@
+use color_quant::NeuQuant;
+
+/// NeuQuant sampling factor, 1 is the best quality, 30 the fastest
+const SAMPLE_FACTOR: i32 = 10;
+
+/// Result of the palette quantization
+pub struct Quantized {
+    pub png: Vec<u8>,
+    /// Peak signal-to-noise ratio in dB against the original pixels, `None` if the quantization was lossless
+    pub psnr: Option<f64>,
+}
+
+/// Quantizes the interleaved G+A or RGB+A pixels to 256 colors and encodes them as an 8-bit indexed PNG.
+///
+/// Transparency is stored in the `tRNS` chunk. If `with_alpha` is false the alpha is ignored and the tile is opaque.
+pub fn encode_png8(
+    data: &[u8],
+    band_count: usize,
+    size: u16,
+    with_alpha: bool,
+) -> Result<Quantized, png::EncodingError> {
+    let rgba: Vec<u8> = data
+        .chunks_exact(band_count)
+        .flat_map(|chunk| {
+            let alpha = if with_alpha {
+                chunk[band_count - 1]
+            } else {
+                255
+            };
+
+            if band_count == 2 {
+                [chunk[0], chunk[0], chunk[0], alpha]
+            } else {
+                [chunk[0], chunk[1], chunk[2], alpha]
+            }
+        })
+        .collect();
+
+    let nq = NeuQuant::new(SAMPLE_FACTOR, 256, &rgba);
+
+    let palette = nq.color_map_rgba();
+
+    let indices: Vec<u8> = rgba
+        .chunks_exact(4)
+        .map(|pixel| nq.index_of(pixel) as u8)
+        .collect();
+
+    let squared_error: u64 = rgba
+        .chunks_exact(4)
+        .zip(&indices)
+        .flat_map(|(pixel, &index)| {
+            let entry = &palette[usize::from(index) * 4..usize::from(index) * 4 + 4];
+
+            pixel
+                .iter()
+                .zip(entry)
+                .map(|(&a, &b)| u64::from(a.abs_diff(b)).pow(2))
+        })
+        .sum();
+
+    let mse = squared_error as f64 / rgba.len() as f64;
+
+    let psnr = (mse > 0.0).then(|| 10.0 * (255.0_f64.powi(2) / mse).log10());
+
+    let mut png = Vec::new();
+
+    let mut encoder = png::Encoder::new(&mut png, u32::from(size), u32::from(size));
+
+    encoder.set_color(png::ColorType::Indexed);
+
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder.set_compression(png::Compression::High);
+
+    encoder.set_palette(
+        palette
+            .chunks_exact(4)
+            .flat_map(|entry| &entry[0..3])
+            .copied()
+            .collect::<Vec<_>>(),
+    );
+
+    if with_alpha {
+        encoder.set_trns(
+            palette
+                .chunks_exact(4)
+                .map(|entry| entry[3])
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    let mut writer = encoder.write_header()?;
+
+    writer.write_image_data(&indices)?;
+
+    writer.finish()?;
+
+    Ok(Quantized { png, psnr })
+}
@@ -0,0 +1,262 @@
+use crate::{error::TilerError, geo::to_wgs84_bounds};
+use rusqlite::{Connection, OpenFlags, OptionalExtension, params_from_iter, types::Value};
+use std::path::Path;
+use tilemath::Tile;
+use tracing::warn;
+
+/// Number of recovered and lost rows of a table
+struct CopyStats {
+    recovered: usize,
+    lost: usize,
+}
+
+/// Copies rows of the table one by one by rowid, skipping unreadable ones. Calls `on_row` with every recovered row.
+fn copy_table(
+    source: &Connection,
+    target: &Connection,
+    table: &str,
+    mut on_row: impl FnMut(&[String], &[Value]),
+) -> Result<CopyStats, TilerError> {
+    let columns = source
+        .prepare(&format!("PRAGMA table_info({table})"))?
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stats = CopyStats {
+        recovered: 0,
+        lost: 0,
+    };
+
+    let Some(max_rowid) = source
+        .query_row(&format!("SELECT max(rowid) FROM {table}"), [], |row| {
+            row.get::<_, Option<i64>>(0)
+        })
+        .unwrap_or_else(|e| {
+            warn!("Failed to read max rowid of {table}: {e}");
+
+            None
+        })
+    else {
+        return Ok(stats);
+    };
+
+    let mut select = source.prepare(&format!(
+        "SELECT {} FROM {table} WHERE rowid = ?1",
+        columns.join(", ")
+    ))?;
+
+    let mut insert = target.prepare(&format!(
+        "INSERT OR IGNORE INTO {table} ({}) VALUES ({})",
+        columns.join(", "),
+        vec!["?"; columns.len()].join(", ")
+    ))?;
+
+    target.execute("BEGIN", [])?;
+
+    for rowid in 1..=max_rowid {
+        let values = select
+            .query_row([rowid], |row| {
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .optional();
+
+        match values {
+            Ok(Some(values)) => {
+                insert.execute(params_from_iter(&values))?;
+
+                on_row(&columns, &values);
+
+                stats.recovered += 1;
+            }
+            Ok(None) => {}
+            Err(_) => {
+                stats.lost += 1;
+            }
+        }
+    }
+
+    target.execute("COMMIT", [])?;
+
+    Ok(stats)
+}
+
+/// Copies all readable tiles (and other tables) of a corrupt MBTiles file to a new one and
+/// re-derives `minzoom`, `maxzoom` and `bounds` metadata from the recovered tiles.
+pub fn recover_from_corrupt_mbtiles(file: &Path, output: &Path) -> Result<(), TilerError> {
+    if !file.exists() {
+        return Err(TilerError::SourceNotFound(file.to_path_buf()));
+    }
+
+    if output.exists() {
+        return Err(TilerError::TargetExists(output.to_path_buf()));
+    }
+
+    let source = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    match source
+        .prepare("PRAGMA integrity_check")
+        .and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()
+        }) {
+        Ok(problems) if problems == ["ok"] => println!("Integrity check passed"),
+        Ok(problems) => {
+            for problem in problems {
+                println!("Integrity check: {problem}");
+            }
+        }
+        Err(e) => println!("Integrity check failed: {e}"),
+    }
+
+    let target = Connection::open(output)?;
+
+    // views (the `tiles` of the de-duplicated layout) are created after the tables they select from
+    let schema = source
+        .prepare(
+            "SELECT type, name, sql FROM sqlite_master WHERE type IN ('table', 'index', 'view') AND sql IS NOT NULL
+              ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'index' THEN 1 ELSE 2 END",
+        )?
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (_, _, sql) in &schema {
+        target.execute(sql, [])?;
+    }
+
+    let tables: Vec<_> = schema
+        .into_iter()
+        .filter(|(kind, _, _)| kind == "table")
+        .map(|(_, name, _)| name)
+        .collect();
+
+    let tile_row_order = source
+        .query_row(
+            "SELECT value FROM metadata WHERE name = 'tile_row_order'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .ok()
+        .flatten();
+
+    let mut zoom_range: Option<(u8, u8)> = None;
+
+    let mut bounds: Option<[f64; 4]> = None;
+
+    for table in tables.iter().filter(|table| {
+        [
+            "metadata",
+            "tiles",
+            "map",
+            "image_data",
+            "checksums",
+            "zstd_dictionary",
+        ]
+        .contains(&table.as_str())
+    }) {
+        let stats = copy_table(&source, &target, table, |columns, values| {
+            // tile coordinates of the plain or the de-duplicated layout
+            if table != "tiles" && table != "map" {
+                return;
+            }
+
+            let get = |name| {
+                columns
+                    .iter()
+                    .position(|column| column == name)
+                    .and_then(|i| match values[i] {
+                        Value::Integer(value) => Some(value),
+                        _ => None,
+                    })
+            };
+
+            let (Some(zoom), Some(x), Some(row)) =
+                (get("zoom_level"), get("tile_column"), get("tile_row"))
+            else {
+                return;
+            };
+
+            // the row is copied but its invalid coordinates can't contribute to the metadata
+            let Some(zoom) = u8::try_from(zoom).ok().filter(|zoom| *zoom < 32) else {
+                return;
+            };
+
+            let size = 1_i64 << zoom;
+
+            if !(0..size).contains(&x) || !(0..size).contains(&row) {
+                return;
+            }
+
+            let tile = Tile {
+                zoom,
+                x: x as u32,
+                y: if tile_row_order.as_deref() == Some("xyz") {
+                    row as u32
+                } else {
+                    (size - 1 - row) as u32
+                },
+            };
+
+            zoom_range = Some(zoom_range.map_or((zoom, zoom), |(min_zoom, max_zoom)| {
+                (min_zoom.min(zoom), max_zoom.max(zoom))
+            }));
+
+            let tile_bounds = tile.bounds(256);
+
+            bounds = Some(bounds.map_or(
+                [
+                    tile_bounds.min_x,
+                    tile_bounds.min_y,
+                    tile_bounds.max_x,
+                    tile_bounds.max_y,
+                ],
+                |bounds| {
+                    [
+                        bounds[0].min(tile_bounds.min_x),
+                        bounds[1].min(tile_bounds.min_y),
+                        bounds[2].max(tile_bounds.max_x),
+                        bounds[3].max(tile_bounds.max_y),
+                    ]
+                },
+            ));
+        })?;
+
+        println!(
+            "Table {table}: recovered {} rows, lost {} rows",
+            stats.recovered, stats.lost
+        );
+    }
+
+    if tables.iter().any(|table| table == "metadata") {
+        if let Some((min_zoom, max_zoom)) = zoom_range {
+            target.execute(
+                "INSERT OR REPLACE INTO metadata (name, value) VALUES ('minzoom', ?1)",
+                [min_zoom],
+            )?;
+
+            target.execute(
+                "INSERT OR REPLACE INTO metadata (name, value) VALUES ('maxzoom', ?1)",
+                [max_zoom],
+            )?;
+        }
+
+        if let Some(bounds) = bounds {
+            let bounds = to_wgs84_bounds(&bounds)?;
+
+            target.execute(
+                "INSERT OR REPLACE INTO metadata (name, value) VALUES ('bounds', ?1)",
+                [bounds.map(|c| format!("{}", c)).join(",")],
+            )?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,2103 @@
+use crate::{
+    Limits,
+    args::{Args, Format, NoData, OutputFormat, TaskQueueStrategy, TileOrdering, TileRowOrder},
+    bbox::{TileRange, covered_tiles_par, covered_tiles_within_polygons, tile_polygon},
+    clip, color, coverage,
+    debug_megatile::DebugMegatile,
+    elevation::ElevationEncoding,
+    error::TilerError,
+    exclusion::ExclusionIndex,
+    geo::{
+        WGS84_TO_WEB_MERCATOR_PIPELINE, compute_band_statistics, compute_bbox, cutline_wkt,
+        is_plausible_extent, is_wgs84, is_within_web_mercator, to_wgs84_bounds, to_wkt2,
+    },
+    geojson::{parse_geojson_polygons, reproject_bbox, reproject_polygons},
+    hilbert, pmtiles,
+    processor::{
+        MegatileCache, MosaicSource, MtimeCheck, Processor, ProcessorChannels, ProcessorConfig,
+    },
+    progress,
+    schema::{
+        RESERVED_METADATA_KEYS, TilesetMetadata, check_tile_data_format_compatibility,
+        insert_metadata, read_metadata, tile_data_format,
+    },
+    source_list::{
+        Source, expand_source_file_pattern, is_vsi_path, open_source, parse_source_file_list,
+    },
+    tile_inserter::{self, DirectoryConfig, InsertMode, InserterConfig, TileListWriter},
+    tile_path::TilePath,
+    tilejson, time_track, tune, validate,
+    warp::{Transform, WarpMethod, WarpOptions},
+    zorder,
+};
+use ::geo::Polygon;
+use crossbeam_deque::{Steal, Stealer, Worker};
+use gdal::{
+    Dataset,
+    programs::raster::build_vrt,
+    raster::ColorInterpretation,
+    spatial_ref::{CoordTransform, CoordTransformOptions, SpatialRef},
+};
+use rayon::iter::ParallelIterator;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, available_parallelism},
+    time::{Duration, Instant, SystemTime},
+};
+use tilemath::{BBox, Tile, WEB_MERCATOR_EXTENT};
+use tracing::{debug, info, warn};
+
+/// Max latitude of Web Mercator
+const MAX_LATITUDE: f64 = 85.06;
+
+/// Default maximum length of SQLite string or blob
+const SQLITE_MAX_LENGTH: usize = 1_000_000_000;
+
+/// Generates the tiles as configured by the arguments, eg. parsed from the command line or built
+/// by [`TilerConfig`](crate::TilerConfig).
+pub fn run(mut args: Args) -> Result<(), TilerError> {
+    let start_instant = Instant::now();
+
+    for (key, value) in &args.gdal_config {
+        gdal::config::set_config_option(key, value)?;
+    }
+
+    if args.retina {
+        let Some(max_zoom) = args.max_zoom.checked_sub(1) else {
+            return Err(TilerError::InvalidArgument(
+                "--retina requires --max-zoom of at least 1".into(),
+            ));
+        };
+
+        args.tile_size *= 2;
+
+        args.max_zoom = max_zoom;
+
+        args.min_zoom = args.min_zoom.saturating_sub(1);
+    }
+
+    if args.min_zoom > args.max_zoom {
+        return Err(TilerError::InvalidArgument(format!(
+            "--min-zoom {} is greater than --max-zoom {}",
+            args.min_zoom, args.max_zoom
+        )));
+    }
+
+    if args.output_format == OutputFormat::Directory {
+        for (used, name) in [
+            (args.continue_file.is_some(), "--continue-file"),
+            (args.incremental, "--incremental"),
+            (args.progress_file.is_some(), "--progress-file"),
+            (args.dedup, "--dedup"),
+            (args.png_post_compress, "--png-post-compress"),
+            (args.store_checksums, "--store-checksums"),
+            (args.tile_crs_wkt, "--tile-crs-wkt"),
+        ] {
+            if used {
+                return Err(TilerError::InvalidArgument(format!(
+                    "{name} can't be used with --output-format directory"
+                )));
+            }
+        }
+
+        if args.format.extension().is_none() {
+            return Err(TilerError::InvalidArgument(
+                "--format auto can't be used with --output-format directory, tiles of mixed formats can't be described by TileJSON".into(),
+            ));
+        }
+    } else if args.write_world_file {
+        return Err(TilerError::InvalidArgument(
+            "--write-world-file requires --output-format directory".into(),
+        ));
+    }
+
+    if args.tile_padding > 0 {
+        for (used, name) in [
+            (args.continue_file.is_some(), "--continue-file"),
+            (args.incremental, "--incremental"),
+            (args.write_world_file, "--write-world-file"),
+            (args.overzoom, "--overzoom"),
+        ] {
+            if used {
+                return Err(TilerError::InvalidArgument(format!(
+                    "{name} can't be used with --tile-padding"
+                )));
+            }
+        }
+    }
+
+    if args.png_post_compress && matches!(args.format, Format::JPEG | Format::WebP | Format::AVIF) {
+        return Err(TilerError::InvalidArgument(
+            "--png-post-compress can't be used with --format jpeg, webp or avif, only PNG tiles are compressed".into(),
+        ));
+    }
+
+    if args.tilejson.is_some() && args.format.extension().is_none() {
+        return Err(TilerError::InvalidArgument(
+            "--format auto can't be used with --tilejson, tiles of mixed formats can't be described by TileJSON".into(),
+        ));
+    }
+
+    if args.output_format == OutputFormat::Pmtiles {
+        for (used, name) in [
+            (args.continue_file.is_some(), "--continue-file"),
+            (args.incremental, "--incremental"),
+            (args.target_file_temp.is_some(), "--target-file-temp"),
+            (args.atomic_swap, "--atomic-swap"),
+            (args.png_post_compress, "--png-post-compress"),
+            (args.store_checksums, "--store-checksums"),
+        ] {
+            if used {
+                return Err(TilerError::InvalidArgument(format!(
+                    "{name} can't be used with --output-format pmtiles"
+                )));
+            }
+        }
+
+        if args.format.extension().is_none() {
+            return Err(TilerError::InvalidArgument(
+                "--format auto can't be used with --output-format pmtiles, the archive has a single tile type".into(),
+            ));
+        }
+    }
+
+    // generate into the temporary file and rename it to the target on completion
+    let final_target_file = args
+        .target_file_temp
+        .clone()
+        .or_else(|| {
+            args.atomic_swap.then(|| {
+                let mut path = args.target_file.clone().into_os_string();
+
+                path.push(".tmp");
+
+                PathBuf::from(path)
+            })
+        })
+        .map(|temp_file| {
+            if cfg!(windows) {
+                warn!(
+                    "Renaming of the temporary file is not atomic on Windows, use a filesystem that supports it."
+                );
+            }
+
+            std::mem::replace(&mut args.target_file, temp_file)
+        });
+
+    // PMTiles is converted from MBTiles generated next to it
+    let pmtiles_file = (args.output_format == OutputFormat::Pmtiles).then(|| {
+        let temp_file = args.target_file.with_extension("pmtiles.mbtiles");
+
+        std::mem::replace(&mut args.target_file, temp_file)
+    });
+
+    if let Some(ref pmtiles_file) = pmtiles_file
+        && pmtiles_file.exists()
+        && !args.print_stats
+        && !args.dry_run
+    {
+        return Err(TilerError::TargetExists(pmtiles_file.clone()));
+    }
+
+    // incremental update continues the target in place
+    if args.incremental {
+        if !args.target_file.exists() {
+            return Err(TilerError::InvalidArgument(
+                "--incremental requires existing --target-file".into(),
+            ));
+        }
+
+        match args.continue_file {
+            Some(ref continue_file) if *continue_file != args.target_file => {
+                return Err(TilerError::InvalidArgument(
+                    "--incremental can't be used with --continue-file other than --target-file"
+                        .into(),
+                ));
+            }
+            _ => args.continue_file = Some(args.target_file.clone()),
+        }
+    }
+
+    let target_file = args.target_file.as_path();
+
+    if target_file.exists() && args.continue_file.is_none() && !args.print_stats && !args.dry_run {
+        return Err(TilerError::TargetExists(target_file.to_path_buf()));
+    }
+
+    let mut metadata = args
+        .metadata_json
+        .as_deref()
+        .map(serde_json::from_str::<HashMap<String, String>>)
+        .transpose()
+        .map_err(|e| TilerError::InvalidMetadata(e.to_string()))?
+        .unwrap_or_default();
+
+    for entry in &args.metadata {
+        let Some((key, value)) = entry.split_once('=') else {
+            return Err(TilerError::InvalidMetadata(format!(
+                "--metadata \"{entry}\" is not in the key=value format"
+            )));
+        };
+
+        metadata.insert(key.to_string(), value.to_string());
+    }
+
+    if !args.allow_metadata_override
+        && let Some(key) = RESERVED_METADATA_KEYS
+            .iter()
+            .find(|key| metadata.contains_key(**key))
+    {
+        return Err(TilerError::InvalidMetadata(format!(
+            "reserved key \"{key}\" can't be overridden without --allow-metadata-override"
+        )));
+    }
+
+    if let Some(ref continue_file) = args.continue_file {
+        if matches!(args.format, Format::AVIF) {
+            return Err(TilerError::InvalidArgument(
+                "--continue-file can't be used with --format avif, AVIF tiles can't be decoded to compose parent tiles".into(),
+            ));
+        }
+
+        let conn = Connection::open_with_flags(continue_file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+        // files created before the metadata was introduced are TMS
+        let tile_row_order =
+            read_metadata(&conn, "tile_row_order")?.unwrap_or_else(|| "tms".into());
+
+        if tile_row_order != args.tile_row_order.as_str() {
+            return Err(TilerError::InvalidArgument(format!(
+                "continue file uses {tile_row_order} tile row order but --tile-row-order is {}",
+                args.tile_row_order.as_str()
+            )));
+        }
+
+        if let Some(existing) = read_metadata(&conn, "tile_data_format")? {
+            check_tile_data_format_compatibility(
+                &existing,
+                &tile_data_format(
+                    args.format,
+                    quality(&args),
+                    args.tile_alpha_storage,
+                    args.alpha_compression_level,
+                    args.png_post_compress,
+                ),
+            )
+            .map_err(TilerError::InvalidArgument)?;
+        }
+    }
+
+    let num_threads = args.num_threads.unwrap_or_else(|| {
+        available_parallelism()
+            .expect("errro getting available parallelism")
+            .get() as u16
+    });
+
+    let mut bounding_polygons = args
+        .bounding_polygon
+        .as_deref()
+        .map(parse_geojson_polygons)
+        .transpose()?;
+
+    bounding_polygons
+        .as_mut()
+        .map(reproject_polygons)
+        .transpose()?;
+
+    // kept in EPSG:4326, transformed to the SRS of every source
+    let cutline = args
+        .cutline
+        .as_deref()
+        .map(parse_geojson_polygons)
+        .transpose()?;
+
+    let bbox = args
+        .bbox
+        .as_deref()
+        .map(|bbox| -> Result<_, TilerError> {
+            let bbox: [f64; 4] = bbox.try_into().map_err(|_| {
+                TilerError::InvalidArgument("--bbox requires 4 coordinates".into())
+            })?;
+
+            if bbox[0] >= bbox[2] || bbox[1] >= bbox[3] {
+                return Err(TilerError::InvalidArgument(
+                    "--bbox minimum must be less than maximum for both axes".into(),
+                ));
+            }
+
+            if bbox[0] < -180.0 || bbox[2] > 180.0 || bbox[1] < -MAX_LATITUDE || bbox[3] > MAX_LATITUDE
+            {
+                return Err(TilerError::InvalidArgument(format!(
+                    "--bbox longitudes must be within [-180, 180] and latitudes within [-{MAX_LATITUDE}, {MAX_LATITUDE}]"
+                )));
+            }
+
+            reproject_bbox(&bbox)
+        })
+        .transpose()?;
+
+    let skip_index = args
+        .skip_tiles_geojson
+        .as_deref()
+        .map(|path| -> Result<_, TilerError> {
+            let mut polygons = parse_geojson_polygons(path)?;
+
+            reproject_polygons(&mut polygons)?;
+
+            Ok(ExclusionIndex::new(polygons))
+        })
+        .transpose()?;
+
+    let mut mosaic_vrt = None;
+
+    let sources = if let Some(ref source_file_list) = args.source_file_list {
+        parse_source_file_list(source_file_list).map_err(TilerError::InvalidSourceFileList)?
+    } else if args.source_file_pattern.is_some() || args.source_file.len() > 1 {
+        let paths = if let Some(ref pattern) = args.source_file_pattern {
+            let paths = expand_source_file_pattern(pattern, args.source_glob_recursive)
+                .map_err(TilerError::InvalidArgument)?;
+
+            for path in &paths {
+                debug!("Matched {}", path.display());
+            }
+
+            paths
+        } else {
+            args.source_file.clone()
+        };
+
+        let datasets = paths
+            .iter()
+            .map(Dataset::open)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let vrt_file = args.target_file.with_extension("mosaic.vrt");
+
+        // dropping the dataset writes the VRT, its extent is the union of the sources
+        drop(build_vrt(Some(&vrt_file), &datasets, None)?);
+
+        mosaic_vrt = Some(vrt_file.clone());
+
+        vec![Source {
+            path: vrt_file,
+            srs: None,
+        }]
+    } else {
+        vec![Source {
+            path: args
+                .source_file
+                .first()
+                .cloned()
+                .expect("source file should be provided"),
+            srs: None,
+        }]
+    };
+
+    if sources.iter().any(|source| is_vsi_path(&source.path)) {
+        for (used, name) in [
+            (
+                args.source_file_mtime_check.is_some(),
+                "--source-file-mtime-check",
+            ),
+            (args.megatile_cache.is_some(), "--megatile-cache"),
+            (args.output_metatile_dir.is_some(), "--output-metatile-dir"),
+        ] {
+            if used {
+                return Err(TilerError::InvalidArgument(format!(
+                    "{name} can't be used with GDAL virtual file system sources, their modification time is unknown"
+                )));
+            }
+        }
+    }
+
+    if args.validate_input {
+        info!("Validating input");
+
+        let instant = Instant::now();
+
+        let mut issue_count = 0;
+
+        for source in &sources {
+            for issue in validate::validate_input(&source.path, args.source_overview_level) {
+                warn!("{}: {issue}", source.path.display());
+
+                issue_count += 1;
+            }
+        }
+
+        info!(
+            "Input validated in {:.1} s",
+            instant.elapsed().as_secs_f64()
+        );
+
+        if issue_count > 0 {
+            return Err(TilerError::InvalidInput(format!(
+                "{issue_count} issue(s) found"
+            )));
+        }
+    }
+
+    if let Some(target_bytes) = args.max_zoom_per_tile_size_bytes {
+        args.max_zoom = cap_max_zoom(&args, &sources[0], target_bytes)?;
+    }
+
+    let limits = Arc::new(Mutex::new(HashMap::<u8, Limits>::new()));
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+
+    // the first signal lets the running tiles finish and the output be finalized, the second one
+    // terminates immediately
+    for signal in [SIGINT, SIGTERM] {
+        signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&interrupted))?;
+
+        signal_hook::flag::register(signal, Arc::clone(&interrupted))?;
+    }
+
+    // all the sources are composited into a single pyramid so that its overviews are built from
+    // the composited tiles
+    if !process_source(
+        &args,
+        &sources,
+        num_threads,
+        Area {
+            bounding_polygons: bounding_polygons.as_deref(),
+            bounding_bbox: bbox.as_ref(),
+            skip_index: skip_index.as_ref(),
+            cutline: cutline.as_deref(),
+        },
+        &limits,
+        &interrupted,
+    )? {
+        return Ok(());
+    }
+
+    if args.dry_run {
+        return Ok(());
+    }
+
+    let mut limits = std::mem::take(&mut *limits.lock().unwrap());
+
+    // a run continuing in place adds to the tiles of the previous runs
+    if args.output_format != OutputFormat::Directory {
+        merge_existing_limits(target_file, &mut limits)?;
+    }
+
+    let tiles_bounds = tiles_bounds(&limits, args.tile_size, args.tile_row_order);
+
+    let tilejson = if args.output_format == OutputFormat::Directory || args.tilejson.is_some() {
+        let tilejson = tilejson::tilejson(
+            &format!(
+                "{{z}}/{{x}}/{{y}}.{}",
+                args.format
+                    .extension()
+                    .expect("format should have extension")
+            ),
+            &args,
+            tiles_bounds
+                .map(|(zoom, bounds)| to_wgs84_bounds(&bounds).map(|bounds| (bounds, zoom)))
+                .transpose()?,
+            &metadata,
+        );
+
+        Some(serde_json::to_string_pretty(&tilejson).expect("TileJSON should be serialized"))
+    } else {
+        None
+    };
+
+    if args.output_format == OutputFormat::Directory {
+        std::fs::write(
+            target_file.join("metadata.json"),
+            tilejson.as_ref().expect("TileJSON should be created"),
+        )?;
+    } else {
+        write_mbtiles_metadata(&args, target_file, &limits, tiles_bounds, &metadata)?;
+    }
+
+    if let Some(ref tilejson_file) = args.tilejson {
+        std::fs::write(
+            tilejson_file,
+            tilejson.as_ref().expect("TileJSON should be created"),
+        )?;
+    }
+
+    // the partial output is neither converted nor renamed so it can be continued
+    if interrupted.load(Ordering::Relaxed) {
+        return Err(TilerError::Interrupted(args.target_file.clone()));
+    }
+
+    if let Some(ref pmtiles_file) = pmtiles_file {
+        pmtiles::convert_mbtiles(&args.target_file, pmtiles_file)?;
+
+        info!("Converted to {}", pmtiles_file.display());
+    }
+
+    if let Some(ref final_target_file) = final_target_file {
+        std::fs::rename(&args.target_file, final_target_file)?;
+
+        info!(
+            "Renamed {} to {}",
+            args.target_file.display(),
+            final_target_file.display()
+        );
+    }
+
+    if args.json_progress {
+        let output_file = final_target_file.as_deref().unwrap_or(&args.target_file);
+
+        let (zoom_counts, output_size) = if args.output_format == OutputFormat::Directory {
+            directory_tile_counts(output_file)?
+        } else {
+            let conn = Connection::open_with_flags(output_file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+            let zoom_counts = conn
+                .prepare("SELECT zoom_level, COUNT(*) FROM tiles GROUP BY zoom_level")?
+                .query_map([], |row| Ok((row.get::<_, u8>(0)?, row.get::<_, u64>(1)?)))?
+                .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+            let output_file = pmtiles_file.as_deref().unwrap_or(output_file);
+
+            (zoom_counts, std::fs::metadata(output_file)?.len())
+        };
+
+        println!(
+            "{}",
+            serde_json::json!({
+                "total_tiles": zoom_counts.values().sum::<u64>(),
+                "elapsed_seconds": start_instant.elapsed().as_secs_f64(),
+                "output_file_size": output_size,
+                "zoom_tile_counts": zoom_counts,
+            })
+        );
+    }
+
+    if pmtiles_file.is_some()
+        && !args.keep_temp_files
+        && let Err(e) = std::fs::remove_file(&args.target_file)
+    {
+        warn!(
+            "Failed to remove temporary file {}: {e}",
+            args.target_file.display()
+        );
+    }
+
+    if let Some(mosaic_vrt) = mosaic_vrt
+        && !args.keep_temp_files
+        && let Err(e) = std::fs::remove_file(&mosaic_vrt)
+    {
+        warn!(
+            "Failed to remove temporary file {}: {e}",
+            mosaic_vrt.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Restrictions of the area to generate the tiles of
+struct Area<'a> {
+    /// Only tiles intersecting the polygons are generated
+    bounding_polygons: Option<&'a [Polygon]>,
+    bounding_bbox: Option<&'a BBox>,
+    /// Tiles intersecting the excluded polygons are skipped
+    skip_index: Option<&'a ExclusionIndex>,
+    /// Pixels outside of the polygons are transparent
+    cutline: Option<&'a [Polygon]>,
+}
+
+/// Generates tiles of the sources composited into a single mosaic (usually just one source). Returns `false` if there is nothing more to do.
+fn process_source(
+    args: &Args,
+    sources: &[Source],
+    num_threads: u16,
+    area: Area,
+    limits: &Arc<Mutex<HashMap<u8, Limits>>>,
+    interrupted: &Arc<AtomicBool>,
+) -> Result<bool, TilerError> {
+    let Area {
+        bounding_polygons,
+        bounding_bbox,
+        skip_index,
+        cutline,
+    } = area;
+
+    let source = &sources[0];
+
+    if !is_vsi_path(&source.path) && !source.path.exists() {
+        return Err(TilerError::SourceNotFound(source.path.clone()));
+    }
+
+    let source_ds = open_source(
+        &source.path,
+        args.source_overview_level,
+        args.palette_nodata_index,
+    )?;
+
+    if args.print_stats {
+        for (i, stats) in compute_band_statistics(&source_ds)?.iter().enumerate() {
+            println!(
+                "Band {}: min: {}, max: {}, mean: {:.3}, std dev: {:.3}",
+                i + 1,
+                stats.min,
+                stats.max,
+                stats.mean,
+                stats.std_dev
+            );
+        }
+
+        return Ok(false);
+    }
+
+    let supported = [
+        vec![ColorInterpretation::GrayIndex],
+        vec![
+            ColorInterpretation::GrayIndex,
+            ColorInterpretation::AlphaBand,
+        ],
+        vec![
+            ColorInterpretation::RedBand,
+            ColorInterpretation::GreenBand,
+            ColorInterpretation::BlueBand,
+        ],
+        vec![
+            ColorInterpretation::RedBand,
+            ColorInterpretation::GreenBand,
+            ColorInterpretation::BlueBand,
+            ColorInterpretation::AlphaBand,
+        ],
+    ]
+    .iter()
+    .any(|colors| {
+        source_ds.raster_count() == colors.len()
+            && colors.iter().enumerate().all(|(i, color)| {
+                source_ds.rasterband(i + 1).unwrap().color_interpretation() == *color
+            })
+    });
+
+    if matches!(args.format, Format::TerrainRgb | Format::Terrarium) {
+        if source_ds.raster_count() != 1 {
+            return Err(TilerError::InvalidArgument(format!(
+                "elevation --format requires single band elevation source but it has {} bands",
+                source_ds.raster_count()
+            )));
+        }
+    } else if !supported {
+        return Err(TilerError::InvalidBandCount(source_ds.raster_count()));
+    }
+
+    if args.source_nodata_mask_band && source_ds.rasterband(1)?.mask_flags()?.is_all_valid() {
+        warn!("Source has no mask band, all pixels will be treated as valid.");
+    }
+
+    for (name, values) in [
+        ("--input-scale", &args.input_scale),
+        ("--input-offset", &args.input_offset),
+    ] {
+        if !values.is_empty() && values.len() != source_ds.raster_count() {
+            return Err(TilerError::InvalidArgument(format!(
+                "{name} has {} value(s) but source has {} band(s)",
+                values.len(),
+                source_ds.raster_count()
+            )));
+        }
+    }
+
+    let no_data = source_no_data(args, &source_ds)?;
+
+    let input_coefficients =
+        (!args.input_scale.is_empty() || !args.input_offset.is_empty()).then(|| {
+            (0..source_ds.raster_count())
+                .map(|i| {
+                    (
+                        args.input_scale.get(i).copied().unwrap_or(1.0),
+                        args.input_offset.get(i).copied().unwrap_or(0.0),
+                    )
+                })
+                .collect()
+        });
+
+    // // delete a tile and parents
+    // {
+    //     let conn =
+    //         Connection::open(target_file).map_err(|e| format!("Error opening output: {e}"))?;
+
+    //     let mut tile = Tile {
+    //         zoom: 20,
+    //         x: 569618,
+    //         y: 360443,
+    //     };
+
+    //     loop {
+    //         conn.execute(
+    //             "DELETE FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+    //             (tile.zoom, tile.x, tile.reversed_y()),
+    //         )
+    //         .map_err(|e| format!("Error inserting limits: {e}"))?;
+
+    //         let Some(parent) = tile.get_parent() else {
+    //             break;
+    //         };
+
+    //         tile = parent;
+    //     }
+    // }
+
+    let (source_srs, transform, bounds) = source_transform(args, source, &source_ds)?;
+
+    let overview_level = if args.use_overviews {
+        auto_overview_level(args, &source.path, &bounds)?
+    } else {
+        args.source_overview_level
+    };
+
+    let source_ds = if overview_level == args.source_overview_level {
+        source_ds
+    } else {
+        info!(
+            "Using overview level {overview_level} of {}",
+            source.path.display()
+        );
+
+        open_source(&source.path, overview_level, args.palette_nodata_index)?
+    };
+
+    let target_srs = SpatialRef::from_epsg(3857)?;
+
+    let bbox = compute_bbox(&source_ds);
+
+    let mut mosaic_sources = vec![MosaicSource {
+        path: source.path.clone(),
+        overview_level,
+        palette_nodata_index: args.palette_nodata_index,
+        transform,
+        bounds,
+        cutline: None,
+        overzoom: overzoom_levels(args, &source.path, &source_ds, &bounds),
+        no_data,
+    }];
+
+    for overlay in &sources[1..] {
+        if !is_vsi_path(&overlay.path) && !overlay.path.exists() {
+            return Err(TilerError::SourceNotFound(overlay.path.clone()));
+        }
+
+        let overlay_ds = open_source(
+            &overlay.path,
+            args.source_overview_level,
+            args.palette_nodata_index,
+        )?;
+
+        if overlay_ds.raster_count() != source_ds.raster_count() {
+            return Err(TilerError::InvalidArgument(format!(
+                "{} has {} band(s) but {} has {}",
+                overlay.path.display(),
+                overlay_ds.raster_count(),
+                source.path.display(),
+                source_ds.raster_count()
+            )));
+        }
+
+        let (overlay_srs, transform, bounds) = source_transform(args, overlay, &overlay_ds)?;
+
+        let overview_level = if args.use_overviews {
+            auto_overview_level(args, &overlay.path, &bounds)?
+        } else {
+            args.source_overview_level
+        };
+
+        let overlay_ds = if overview_level == args.source_overview_level {
+            overlay_ds
+        } else {
+            info!(
+                "Using overview level {overview_level} of {}",
+                overlay.path.display()
+            );
+
+            open_source(&overlay.path, overview_level, args.palette_nodata_index)?
+        };
+
+        mosaic_sources.push(MosaicSource {
+            path: overlay.path.clone(),
+            overview_level,
+            palette_nodata_index: args.palette_nodata_index,
+            transform,
+            bounds,
+            cutline: cutline
+                .map(|cutline| cutline_wkt(cutline, &overlay_srs, &overlay_ds))
+                .transpose()?,
+            overzoom: overzoom_levels(args, &overlay.path, &overlay_ds, &bounds),
+            no_data: source_no_data(args, &overlay_ds)?,
+        });
+    }
+
+    // union of all the mosaic sources
+    let bounds = mosaic_sources
+        .iter()
+        .map(|source| source.bounds)
+        .reduce(|a, b| {
+            [
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]
+        })
+        .expect("mosaic sources should not be empty");
+
+    info!("Computing tile coverage");
+
+    let mut coverage_bbox = BBox {
+        min_x: bounds[0],
+        max_x: bounds[2],
+        min_y: bounds[1],
+        max_y: bounds[3],
+    };
+
+    if let Some(bbox) = bounding_bbox {
+        coverage_bbox = BBox {
+            min_x: coverage_bbox.min_x.max(bbox.min_x),
+            max_x: coverage_bbox.max_x.min(bbox.max_x),
+            min_y: coverage_bbox.min_y.max(bbox.min_y),
+            max_y: coverage_bbox.max_y.min(bbox.max_y),
+        };
+
+        if coverage_bbox.min_x >= coverage_bbox.max_x || coverage_bbox.min_y >= coverage_bbox.max_y
+        {
+            return Err(TilerError::InvalidArgument(
+                "--bbox doesn't intersect the source".into(),
+            ));
+        }
+    }
+
+    let mut tiles: Vec<_> = if let Some(bounding_polygons) = bounding_polygons {
+        covered_tiles_within_polygons(
+            &coverage_bbox,
+            args.max_zoom,
+            args.tile_size,
+            bounding_polygons,
+        )
+        .collect()
+    } else {
+        covered_tiles_par(&coverage_bbox, args.max_zoom).collect()
+    };
+
+    if let Some(skip_index) = skip_index {
+        let count = tiles.len();
+
+        tiles.retain(|tile| !skip_index.intersects(&tile_polygon(tile, args.tile_size)));
+
+        info!(
+            "Skipped {} tile(s) intersecting --skip-tiles-geojson",
+            count - tiles.len()
+        );
+    }
+
+    if let Some(ref tile_filter) = args.tile_filter_expr {
+        let count = tiles.len();
+
+        // tiles not matching are still generated to compose their matching ancestors
+        tiles.retain(|tile| tile_filter.matches_or_ancestor(tile, args.min_zoom));
+
+        info!(
+            "Skipped {} tile(s) not needed by tiles matching --tile-filter-expr",
+            count - tiles.len()
+        );
+    }
+
+    let clip_file = if args.pre_clip_source && !args.dry_run && !tiles.is_empty() {
+        let clip_file = args.target_file.with_extension(format!(
+            "{}.clip.tif",
+            source
+                .path
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+        ));
+
+        info!("Pre-clipping source to {}", clip_file.display());
+
+        // pad by a tile to leave some context for the resampling
+        let pad = tiles[0].bounds(args.tile_size).max_x - tiles[0].bounds(args.tile_size).min_x;
+
+        let tiles_bbox =
+            tiles
+                .iter()
+                .fold([f64::MAX, f64::MAX, f64::MIN, f64::MIN], |acc, tile| {
+                    let bounds = tile.bounds(args.tile_size);
+
+                    [
+                        acc[0].min(bounds.min_x - pad),
+                        acc[1].min(bounds.min_y - pad),
+                        acc[2].max(bounds.max_x + pad),
+                        acc[3].max(bounds.max_y + pad),
+                    ]
+                });
+
+        let mut inverse_options = CoordTransformOptions::new()?;
+
+        if let Some(ref pipeline) = args.transform_pipeline {
+            inverse_options.set_coordinate_operation(pipeline, true)?;
+        }
+
+        let clip_bbox =
+            CoordTransform::new_with_options(&target_srs, &source_srs, &inverse_options)?
+                .transform_bounds(&tiles_bbox, 21)?;
+
+        clip::clip_to_geotiff(
+            &source_ds,
+            &BBox {
+                min_x: clip_bbox[0].max(bbox.min_x),
+                min_y: clip_bbox[1].max(bbox.min_y),
+                max_x: clip_bbox[2].min(bbox.max_x),
+                max_y: clip_bbox[3].min(bbox.max_y),
+            },
+            &clip_file,
+        )?;
+
+        Some(clip_file)
+    } else {
+        None
+    };
+
+    let source_ds = if let Some(ref clip_file) = clip_file {
+        mosaic_sources[0].path = clip_file.clone();
+
+        // the clip is already made from the overview
+        mosaic_sources[0].overview_level = 0;
+
+        Dataset::open(clip_file)?
+    } else {
+        source_ds
+    };
+
+    // pixel coordinates of the pre-clipped source differ from the original
+    if let Some(cutline) = cutline {
+        mosaic_sources[0].cutline = Some(cutline_wkt(cutline, &source_srs, &source_ds)?);
+    }
+
+    if let Some(ref coverage_geojson) = args.coverage_geojson {
+        info!("Writing tile coverage to {}", coverage_geojson.display());
+
+        coverage::write_coverage_geojson(coverage_geojson, &tiles, args.tile_size)?;
+    }
+
+    info!("Sorting tiles");
+
+    match args.tile_ordering {
+        TileOrdering::Zorder => zorder::sort_by_zorder(&mut tiles),
+        TileOrdering::Hilbert => hilbert::sort_by_hilbert(&mut tiles),
+    }
+
+    let warp_zoom_offset = if args.auto_tune_warp_offset && !args.dry_run {
+        // reuse the offset tuned by the previous run
+        let cached = args
+            .continue_file
+            .as_deref()
+            .map(|continue_file| {
+                Connection::open_with_flags(continue_file, OpenFlags::SQLITE_OPEN_READ_ONLY)
+                    .and_then(|conn| read_metadata(&conn, "warp_zoom_offset"))
+            })
+            .transpose()?
+            .flatten()
+            .and_then(|value| value.parse().ok());
+
+        if let Some(cached) = cached {
+            info!("Using tuned warp zoom offset {cached}");
+
+            cached
+        } else {
+            info!("Tuning warp zoom offset");
+
+            let tuned = tune::tune_warp_zoom_offset(
+                &source_ds,
+                &tiles,
+                args.tile_size,
+                &warp_options(args, &mosaic_sources[0].transform),
+            )
+            .unwrap_or(args.warp_zoom_offset);
+
+            info!("Using warp zoom offset {tuned}");
+
+            tuned
+        }
+    } else {
+        args.warp_zoom_offset
+    };
+
+    info!("Preparing queues");
+
+    let mut pending_set: HashSet<_> = tiles.iter().copied().collect();
+
+    {
+        let mut todo_set: HashSet<_> = tiles.iter().copied().collect();
+        let mut todo_dq: VecDeque<_> = tiles.iter().copied().collect();
+
+        while let Some(tile) = todo_dq.pop_front() {
+            todo_set.remove(&tile);
+
+            if tile.zoom <= args.min_zoom {
+                continue;
+            }
+
+            if let Some(parent_tile) = tile.parent()
+                && args.tile_filter_expr.as_ref().is_none_or(|tile_filter| {
+                    tile_filter.matches_or_ancestor(&parent_tile, args.min_zoom)
+                })
+                && todo_set.insert(parent_tile)
+            {
+                todo_dq.push_back(parent_tile);
+
+                pending_set.insert(parent_tile);
+            }
+        }
+    }
+
+    let mut processed_set = match args.progress_file {
+        Some(ref progress_file) if progress_file.exists() => {
+            if args.continue_file.as_deref() != Some(args.target_file.as_path()) {
+                return Err(TilerError::InvalidArgument(format!(
+                    "progress file {} exists, continue with --continue-file same as --target-file or remove it",
+                    progress_file.display()
+                )));
+            }
+
+            let processed = progress::read_processed(progress_file)?;
+
+            // tiles with unprocessed parent are resumed from the continued file to compose it
+            let done: HashSet<_> = processed
+                .iter()
+                .filter(|tile| {
+                    tile.zoom <= args.min_zoom
+                        || tile
+                            .parent()
+                            .is_some_and(|parent| processed.contains(&parent))
+                })
+                .copied()
+                .collect();
+
+            pending_set.retain(|tile| !done.contains(tile));
+
+            tiles.retain(|tile| !done.contains(tile));
+
+            // lower zoom tiles with all children done are ready
+            tiles.extend(pending_set.iter().filter(|tile| {
+                tile.zoom < args.max_zoom
+                    && tile
+                        .children()
+                        .iter()
+                        .all(|child| !pending_set.contains(child))
+            }));
+
+            info!(
+                "Skipping {} tiles processed by the previous run",
+                done.len()
+            );
+
+            done
+        }
+        _ => HashSet::new(),
+    };
+
+    if args.incremental {
+        let existing = existing_tiles(&args.target_file, args.tile_row_order, &pending_set)?;
+
+        let mut existing: Vec<_> = existing.into_iter().collect();
+
+        existing.sort_unstable_by_key(|tile| std::cmp::Reverse(tile.zoom));
+
+        // a tile is skipped if it exists and so do all its pending descendants
+        let mut skipped = HashSet::new();
+
+        for tile in existing {
+            if tile.zoom == args.max_zoom
+                || tile
+                    .children()
+                    .iter()
+                    .all(|child| !pending_set.contains(child) || skipped.contains(child))
+            {
+                skipped.insert(tile);
+            }
+        }
+
+        // skipped tiles of a composed parent are resumed from the target to compose it
+        let resumed: HashSet<_> = skipped
+            .iter()
+            .filter(|tile| {
+                tile.parent().is_some_and(|parent| {
+                    pending_set.contains(&parent) && !skipped.contains(&parent)
+                })
+            })
+            .copied()
+            .collect();
+
+        skipped.retain(|tile| !resumed.contains(tile));
+
+        pending_set.retain(|tile| !skipped.contains(tile));
+
+        tiles.retain(|tile| !skipped.contains(tile));
+
+        info!("Skipping {} tiles present in the target", skipped.len());
+
+        processed_set.extend(skipped);
+    }
+
+    if args.dry_run {
+        print_dry_run_report(
+            args,
+            &source_ds,
+            &mosaic_sources[0].transform,
+            &coverage_bbox,
+            &pending_set,
+        )?;
+
+        return Ok(true);
+    }
+
+    let workers: Vec<_> = (0..num_threads)
+        .map(|_| match args.task_queue_strategy {
+            TaskQueueStrategy::Lifo => Worker::new_lifo(),
+            TaskQueueStrategy::Fifo => Worker::new_fifo(),
+        })
+        .collect();
+
+    // populate workers
+    'outer: for _ in 0..num_threads {
+        let mut task_tiles = Vec::new();
+
+        let mut key: Option<Tile> = None;
+
+        loop {
+            let Some(tile) = tiles.pop() else {
+                if !task_tiles.is_empty() {
+                    workers[0].push(task_tiles);
+                }
+
+                break 'outer;
+            };
+
+            let curr_key = tile.ancestor(warp_zoom_offset);
+
+            let Some(curr_key) = curr_key else {
+                // no parent
+                workers[0].push(vec![tile]);
+
+                break;
+            };
+
+            if key.is_none() {
+                key = Some(curr_key);
+            }
+
+            if Some(curr_key) == key {
+                task_tiles.push(tile);
+            } else {
+                tiles.push(tile); // return it back
+
+                workers[0].push(task_tiles);
+
+                break;
+            }
+        }
+    }
+
+    let mtime_check = args
+        .source_file_mtime_check
+        .map(|action| -> Result<_, TilerError> {
+            Ok(MtimeCheck {
+                action,
+                interval: args.mtime_check_interval as usize,
+                mtimes: sources
+                    .iter()
+                    .map(|source| {
+                        Ok((
+                            source.path.clone(),
+                            std::fs::metadata(&source.path)
+                                .and_then(|metadata| metadata.modified())?,
+                        ))
+                    })
+                    .collect::<Result<_, io::Error>>()?,
+            })
+        })
+        .transpose()?;
+
+    let megatile_cache = args
+        .megatile_cache
+        .as_ref()
+        .map(|dir| (dir, false))
+        .or_else(|| args.output_metatile_dir.as_ref().map(|dir| (dir, true)))
+        .map(|(dir, shared)| -> Result<_, TilerError> {
+            let mut source_mtime = SystemTime::UNIX_EPOCH;
+
+            for source in sources {
+                let mtime = std::fs::metadata(&source.path)
+                    .and_then(|metadata| metadata.modified())
+                    .map_err(|e| {
+                        TilerError::InvalidArgument(format!(
+                            "error reading modification time of {}: {e}",
+                            source.path.display()
+                        ))
+                    })?;
+
+                source_mtime = source_mtime.max(mtime);
+            }
+
+            Ok(MegatileCache {
+                dir: dir.clone(),
+                source_mtime,
+                shared,
+            })
+        })
+        .transpose()?;
+
+    let debug_megatile = args.debug_megatile.map(|tile| {
+        if tile.zoom.checked_add(warp_zoom_offset) != Some(args.max_zoom) {
+            warn!(
+                "Debug megatile {tile} is not at zoom {} of the megatiles",
+                args.max_zoom.saturating_sub(warp_zoom_offset)
+            );
+        }
+
+        DebugMegatile {
+            tile,
+            prefix: args.target_file.with_extension(""),
+            alpha: args.debug_megatile_alpha,
+        }
+    });
+
+    let progress_bar = args.progress_bar && io::stdout().is_terminal();
+
+    let (stats_tx, stats_collector_thread) = time_track::new(
+        args.debug,
+        args.contention_threshold_pct,
+        progress_bar,
+        args.json_progress,
+    );
+
+    let target_file = args.target_file.as_path();
+
+    let continues_in_place = args.continue_file.as_deref() == Some(target_file);
+
+    let (progress_tx, progress_thread) = args
+        .progress_file
+        .clone()
+        .map(|progress_file| {
+            progress::start_writer(
+                progress_file,
+                Duration::from_secs(args.progress_interval.into()),
+                processed_set.clone(),
+            )
+        })
+        .unzip();
+
+    let insert_mode = if args.incremental || continues_in_place {
+        // parents of the generated tiles are composed again, tiles failing the checksum
+        // verification are rendered again
+        InsertMode::ReplaceOnConflict
+    } else {
+        InsertMode::Insert
+    };
+
+    let tile_list = args
+        .emit_tile_list
+        .as_deref()
+        .map(|path| TileListWriter::open(path, args.emit_tile_list_flush_interval as usize))
+        .transpose()?;
+
+    let (insert_thread, data_tx) = match args.output_format {
+        OutputFormat::Mbtiles | OutputFormat::Pmtiles => tile_inserter::new(
+            target_file,
+            InserterConfig {
+                zoom_range: if !continues_in_place {
+                    Some((args.min_zoom, args.max_zoom))
+                } else {
+                    None
+                },
+                num_threads,
+                format: args.format,
+                store_checksums: args.store_checksums,
+                insert_mode,
+                png_post_compress: args.png_post_compress,
+                tile_row_order: args.tile_row_order,
+                dedup: args.dedup,
+                batch_size: args.batch_size.into(),
+                metadata: TilesetMetadata {
+                    name: args.output_name.clone(),
+                    description: args.output_description.clone(),
+                    attribution: args.output_attribution.clone(),
+                    bounds: to_wgs84_bounds(&bounds)?,
+                    tile_data_format: tile_data_format(
+                        args.format,
+                        quality(args),
+                        args.tile_alpha_storage,
+                        args.alpha_compression_level,
+                        args.png_post_compress,
+                    ),
+                },
+            },
+            stats_tx.clone(),
+            tile_list,
+            progress_tx.clone(),
+        )?,
+        OutputFormat::Directory => tile_inserter::new_directory(
+            target_file,
+            DirectoryConfig {
+                num_threads,
+                ext: args
+                    .format
+                    .extension()
+                    .expect("format should have extension"),
+                insert_mode,
+                tile_row_order: args.tile_row_order,
+                world_file_tile_size: args.write_world_file.then_some(args.tile_size),
+            },
+            stats_tx.clone(),
+            tile_list,
+        )?,
+    };
+
+    if args.auto_tune_warp_offset && !args.dry_run && args.output_format != OutputFormat::Directory
+    {
+        // store the tuned offset before tiling so that an interrupted run doesn't tune it again
+        Connection::open(target_file)?.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('warp_zoom_offset', ?1)",
+            [warp_zoom_offset],
+        )?;
+    }
+
+    let failure = {
+        let processor = &Processor::new(
+            ProcessorConfig {
+                tile_size: args.tile_size,
+                padding: args.tile_padding,
+                min_zoom: args.min_zoom,
+                max_zoom: args.max_zoom,
+                continue_file: args.continue_file.clone(),
+                resume_db_connections: args
+                    .resume_db_connections
+                    .map_or((num_threads / 2).max(1), u16::from)
+                    as usize,
+                // debug output of processing steps would garble the progress bar
+                debug: args.debug && !progress_bar,
+                source_priority: args.source_file_priority,
+                megatile_cache,
+                buffer_cache_budget_bytes: args.cache_size_mb << 20,
+                mtime_check,
+                debug_megatile,
+                use_mask_band: args.source_nodata_mask_band,
+                warp_method: warp_method(args),
+                warp_max_error: args.reproject_error_threshold,
+                resampling_algorithm: args.resampling_algorithm,
+                resampling_algorithm_per_zoom: args
+                    .resampling_algorithm_per_zoom
+                    .iter()
+                    .copied()
+                    .collect(),
+                warp_memory_mb: args.warp_memory_mb,
+                warp_read_strips: args.warp_read_strips,
+                jpeg_quality: args.jpeg_quality,
+                webp_quality: args.webp_quality,
+                avif_quality: args.avif_quality,
+                avif_speed: args.avif_speed,
+                jpeg_subsampling: args.jpeg_subsampling,
+                alpha_compression_level: args.alpha_compression_level,
+                jpeg_restart_interval: args.jpeg_restart_interval,
+                zoom_offset: warp_zoom_offset,
+                insert_empty: args.insert_empty,
+                skip_zoom: args.skip_zoom.clone(),
+                tile_filter: args.tile_filter_expr.clone(),
+                blend_edges: args.tile_blend_edges,
+                format: args.format,
+                tile_row_order: args.tile_row_order,
+                auto_format_threshold: args.auto_format_threshold,
+                alpha_storage: args.tile_alpha_storage,
+                input_coefficients,
+                color_table: args.color_correct.map(|correction| {
+                    color::lookup_table(
+                        correction,
+                        args.color_contrast,
+                        args.color_midpoint,
+                        args.color_gamma,
+                    )
+                }),
+                elevation_encoding: match args.format {
+                    Format::TerrainRgb => Some(ElevationEncoding::TerrainRgb {
+                        base_offset: args.terrain_base_offset,
+                        scale: args.terrain_scale,
+                    }),
+                    Format::Terrarium => Some(ElevationEncoding::Terrarium),
+                    _ => None,
+                },
+                alpha_threshold: args.alpha_threshold,
+            },
+            mosaic_sources,
+            ProcessorChannels {
+                stats_tx,
+                data_tx,
+                progress_tx,
+                limits: Arc::clone(limits),
+                interrupted: Arc::clone(interrupted),
+            },
+            pending_set,
+            tiles,
+            processed_set,
+        );
+
+        info!("Generating tiles");
+
+        thread::scope(|scope| {
+            let stealers: Arc<Vec<_>> = Arc::new(workers.iter().map(Worker::stealer).collect());
+
+            for worker in workers {
+                let stealers = Arc::clone(&stealers);
+
+                scope.spawn(move || {
+                    loop {
+                        // First, try to pop a task from the local worker
+                        if let Some(task) = worker.pop() {
+                            processor.process_task(task, &worker);
+                        }
+                        // If no tasks locally, try to steal from other threads
+                        else if let Steal::Success(task) =
+                            stealers.iter().map(Stealer::steal).collect::<Steal<_>>()
+                        {
+                            processor.process_task(task, &worker);
+                        }
+                        // If no tasks are left anywhere, exit the loop
+                        else {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        let failure = processor.take_failure();
+
+        if interrupted.load(Ordering::Relaxed) && failure.is_none() {
+            warn!("Interrupted, finishing the output");
+        }
+
+        let peak_memory = processor.peak_memory();
+
+        info!(
+            megatile_bytes = peak_memory.megatile_bytes,
+            out_buffer_bytes = peak_memory.out_buffer_bytes,
+            buffer_cache_bytes = peak_memory.buffer_cache_bytes,
+            "Peak memory"
+        );
+
+        failure
+    };
+
+    insert_thread.join().expect("error joining insert_thread");
+
+    if let Some(progress_thread) = progress_thread {
+        progress_thread
+            .join()
+            .expect("error joining progress_thread");
+    }
+
+    // all tiles are stored, nothing to recover
+    if let Some(ref progress_file) = args.progress_file
+        && !interrupted.load(Ordering::Relaxed)
+        && let Err(e) = std::fs::remove_file(progress_file)
+        && e.kind() != io::ErrorKind::NotFound
+    {
+        warn!("Error removing progress file: {e}");
+    }
+
+    stats_collector_thread
+        .join()
+        .expect("error joining stats_collector_thread");
+
+    if let Some(clip_file) = clip_file
+        && !args.keep_temp_files
+    {
+        drop(source_ds);
+
+        if let Err(e) = std::fs::remove_file(&clip_file) {
+            warn!(
+                "Failed to remove temporary file {}: {e}",
+                clip_file.display()
+            );
+        }
+    }
+
+    // the stored tiles and the progress file are kept to continue the run
+    if let Some(e) = failure {
+        return Err(e);
+    }
+
+    Ok(true)
+}
+
+/// Writes metadata of the generated tiles to the MBTiles file.
+fn write_mbtiles_metadata(
+    args: &Args,
+    target_file: &Path,
+    limits: &HashMap<u8, Limits>,
+    tiles_bounds: Option<(u8, [f64; 4])>,
+    metadata: &HashMap<String, String>,
+) -> Result<(), TilerError> {
+    let conn = Connection::open(target_file)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO metadata (name, value) VALUES ('limits', ?1)",
+        [serde_json::to_string(limits).expect("Error serializing limits")],
+    )?;
+
+    // no tiles were inserted if there are no limits
+    if let Some((zoom, bounds)) = tiles_bounds {
+        let bounds = to_wgs84_bounds(&bounds)?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('bounds', ?1)",
+            [bounds.map(|c| format!("{}", c)).join(",")],
+        )?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('center', ?1)",
+            [format!(
+                "{},{},{zoom}",
+                (bounds[0] + bounds[2]) / 2.0,
+                (bounds[1] + bounds[3]) / 2.0
+            )],
+        )?;
+    }
+
+    if let Some(tile_expiry_hours) = args.tile_expiry_hours {
+        let generated_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .expect("system time should be after Unix epoch")
+            .as_secs();
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('tile_expiry_hours', ?1)",
+            [tile_expiry_hours],
+        )?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('generated_at', ?1)",
+            [generated_at],
+        )?;
+    }
+
+    if args.retina {
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('retina', 'true')",
+            [],
+        )?;
+    }
+
+    if args.tile_padding > 0 {
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('tile_size', ?1)",
+            [args.tile_size + 2 * args.tile_padding],
+        )?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('tile_padding', ?1)",
+            [args.tile_padding],
+        )?;
+    }
+
+    if args.tile_crs_wkt {
+        let crs_wkt = to_wkt2(&SpatialRef::from_epsg(3857)?)?;
+
+        if crs_wkt.len() > SQLITE_MAX_LENGTH {
+            return Err(TilerError::InvalidMetadata("crs_wkt is too long".into()));
+        }
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('crs_wkt', ?1)",
+            [crs_wkt],
+        )?;
+    }
+
+    insert_metadata(&conn, metadata)?;
+
+    Ok(())
+}
+
+/// Extends the limits by the ones stored in the MBTiles file by the previous runs.
+fn merge_existing_limits(
+    target_file: &Path,
+    limits: &mut HashMap<u8, Limits>,
+) -> Result<(), TilerError> {
+    let conn = Connection::open_with_flags(target_file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let Some(existing) = conn
+        .query_row(
+            "SELECT value FROM metadata WHERE name = 'limits'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?
+    else {
+        return Ok(());
+    };
+
+    let existing: HashMap<u8, Limits> = serde_json::from_str(&existing)
+        .map_err(|e| TilerError::InvalidMetadata(format!("invalid limits: {e}")))?;
+
+    for (zoom, existing) in existing {
+        limits
+            .entry(zoom)
+            .and_modify(|limits| {
+                limits.min_x = limits.min_x.min(existing.min_x);
+                limits.max_x = limits.max_x.max(existing.max_x);
+                limits.min_y = limits.min_y.min(existing.min_y);
+                limits.max_y = limits.max_y.max(existing.max_y);
+            })
+            .or_insert(existing);
+    }
+
+    Ok(())
+}
+
+/// Returns number of tiles by zoom and total size of the files of the directory output.
+fn directory_tile_counts(dir: &Path) -> io::Result<(BTreeMap<u8, u64>, u64)> {
+    let mut counts = BTreeMap::new();
+
+    let mut size = 0;
+
+    for zoom_entry in std::fs::read_dir(dir)? {
+        let zoom_entry = zoom_entry?;
+
+        if !zoom_entry.file_type()?.is_dir() {
+            size += zoom_entry.metadata()?.len();
+
+            continue;
+        }
+
+        for x_entry in std::fs::read_dir(zoom_entry.path())? {
+            for entry in std::fs::read_dir(x_entry?.path())? {
+                let path = entry?.path();
+
+                size += std::fs::metadata(&path)?.len();
+
+                // skip world files
+                let is_tile = path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| ["jpg", "png", "webp", "avif"].contains(&ext));
+
+                if is_tile && let Some(tile) = Tile::from_path(&path) {
+                    *counts.entry(tile.zoom).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    Ok((counts, size))
+}
+
+/// Returns quality of the lossy format.
+fn quality(args: &Args) -> u8 {
+    match args.format {
+        Format::WebP => args.webp_quality,
+        Format::AVIF => args.avif_quality,
+        _ => args.jpeg_quality,
+    }
+}
+
+/// Prints number of pending tiles to store per zoom level and the output size estimated from
+/// the average size of the tiles sampled at max zoom.
+fn print_dry_run_report(
+    args: &Args,
+    source_ds: &Dataset,
+    transform: &Transform,
+    coverage_bbox: &BBox,
+    pending_set: &HashSet<Tile>,
+) -> Result<(), TilerError> {
+    let mut counts = BTreeMap::<u8, usize>::new();
+
+    for tile in pending_set {
+        if !args.skip_zoom.contains(&tile.zoom)
+            && args
+                .tile_filter_expr
+                .as_ref()
+                .is_none_or(|tile_filter| tile_filter.matches(tile))
+        {
+            *counts.entry(tile.zoom).or_default() += 1;
+        }
+    }
+
+    for (zoom, count) in &counts {
+        println!("Zoom {zoom}: {count} tiles");
+    }
+
+    let total: usize = counts.values().sum();
+
+    println!("Total: {total} tiles");
+
+    let sample = tune::sample_tile_size(
+        source_ds,
+        &TileRange::from_bbox(coverage_bbox, args.max_zoom),
+        args.tile_size,
+        &warp_options(args, transform),
+        args.format,
+        quality(args),
+        args.avif_speed,
+    )
+    .map_err(|code| TilerError::WarpFailed {
+        tile: Tile {
+            zoom: args.max_zoom,
+            x: 0,
+            y: 0,
+        },
+        code,
+    })?;
+
+    if let Some((sampled, average_size)) = sample {
+        println!(
+            "Estimated size: {:.1} MiB (average tile size {average_size:.0} B of {sampled} sampled tiles)",
+            total as f64 * average_size / f64::from(1 << 20)
+        );
+    } else {
+        println!("Estimated size: unknown, no non-empty tiles sampled");
+    }
+
+    Ok(())
+}
+
+/// Returns nodata values of the source bands as bytes, overridden by `--nodata` or
+/// `--nodata-per-band`.
+fn source_no_data(args: &Args, source_ds: &Dataset) -> Result<Vec<Option<u8>>, TilerError> {
+    if !args.nodata_per_band.is_empty() && args.nodata_per_band.len() != source_ds.raster_count() {
+        return Err(TilerError::InvalidArgument(format!(
+            "--nodata-per-band has {} value(s) but source has {} band(s)",
+            args.nodata_per_band.len(),
+            source_ds.raster_count()
+        )));
+    }
+
+    // warped bands are read as bytes
+    let to_byte = |value: f64| {
+        (value.fract() == 0.0 && (0.0..=255.0).contains(&value)).then_some(value as u8)
+    };
+
+    let no_data_override = if !args.nodata_per_band.is_empty() {
+        Some(args.nodata_per_band.iter().copied().map(Some).collect())
+    } else {
+        match args.nodata {
+            Some(NoData::None) => Some(vec![None; source_ds.raster_count()]),
+            Some(NoData::Value(value)) => Some(vec![Some(value); source_ds.raster_count()]),
+            None => None,
+        }
+    };
+
+    if let Some(no_data_override) = no_data_override {
+        no_data_override
+            .into_iter()
+            .map(|value: Option<f64>| {
+                value
+                    .map(|value| {
+                        to_byte(value).ok_or_else(|| {
+                            TilerError::InvalidArgument(format!(
+                                "nodata value {value} is not a byte, only 8-bit nodata values are supported"
+                            ))
+                        })
+                    })
+                    .transpose()
+            })
+            .collect()
+    } else {
+        // nodata of other than 8-bit sources can't match the bytes
+        source_ds
+            .rasterbands()
+            .map(|band| Ok(band?.no_data_value().and_then(to_byte)))
+            .collect()
+    }
+}
+
+fn warp_method(args: &Args) -> WarpMethod {
+    if args.force_reproject_image {
+        WarpMethod::ReprojectImage
+    } else if args.force_chunk_warp {
+        WarpMethod::ChunkAndWarp
+    } else {
+        WarpMethod::Auto
+    }
+}
+
+/// Returns options of warping the source for sampling, without the cutline and per zoom resampling.
+fn warp_options<'a>(args: &Args, transform: &'a Transform) -> WarpOptions<'a> {
+    WarpOptions {
+        transform,
+        use_mask_band: args.source_nodata_mask_band,
+        method: warp_method(args),
+        max_error: args.reproject_error_threshold,
+        resampling: args.resampling_algorithm,
+        memory_limit_mb: args.warp_memory_mb,
+        cutline: None,
+    }
+}
+
+/// Returns level of the coarsest overview of the source with pixels at most as large as the pixels
+/// of the max zoom tiles, 0 for the full resolution. `bounds` are the source bounds in EPSG:3857.
+fn auto_overview_level(args: &Args, path: &Path, bounds: &[f64; 4]) -> Result<u8, TilerError> {
+    let tile_resolution =
+        2.0 * WEB_MERCATOR_EXTENT / (f64::from(args.tile_size) * 2_f64.powi(args.max_zoom.into()));
+
+    // overviews of the file itself, also of a palette-indexed one
+    let source_ds = Dataset::open(path)?;
+
+    let band = source_ds.rasterband(1)?;
+
+    let mut level = 0;
+
+    // overviews are ordered from the most detailed one
+    for index in 0..band.overview_count()? as usize {
+        let (width, _) = band.overview(index)?.size();
+
+        if (bounds[2] - bounds[0]) / width as f64 > tile_resolution {
+            break;
+        }
+
+        level = index + 1;
+    }
+
+    Ok(level as u8)
+}
+
+/// Returns number of zoom levels by which the max zoom tiles exceed the resolution of the source
+/// with `--overzoom`, 0 otherwise. `bounds` are the source bounds in EPSG:3857.
+fn overzoom_levels(args: &Args, path: &Path, source_ds: &Dataset, bounds: &[f64; 4]) -> u8 {
+    if !args.overzoom {
+        return 0;
+    }
+
+    let tile_resolution =
+        2.0 * WEB_MERCATOR_EXTENT / (f64::from(args.tile_size) * 2_f64.powi(args.max_zoom.into()));
+
+    let (width, _) = source_ds.raster_size();
+
+    let source_resolution = (bounds[2] - bounds[0]) / width as f64;
+
+    let levels = (source_resolution / tile_resolution)
+        .log2()
+        .floor()
+        .max(0.0) as u8;
+
+    if levels > 0 {
+        info!("Overzooming {} by {levels} zoom level(s)", path.display());
+    }
+
+    levels
+}
+
+/// Decreases max zoom until the average encoded size of the tiles sampled from the source is below the target.
+fn cap_max_zoom(args: &Args, source: &Source, target_bytes: usize) -> Result<u8, TilerError> {
+    let source_ds = open_source(
+        &source.path,
+        args.source_overview_level,
+        args.palette_nodata_index,
+    )?;
+
+    let (_, transform, bounds) = source_transform(args, source, &source_ds)?;
+
+    let bbox = BBox {
+        min_x: bounds[0],
+        max_x: bounds[2],
+        min_y: bounds[1],
+        max_y: bounds[3],
+    };
+
+    let mut max_zoom = args.max_zoom;
+
+    loop {
+        let sample = tune::sample_tile_size(
+            &source_ds,
+            &TileRange::from_bbox(&bbox, max_zoom),
+            args.tile_size,
+            &warp_options(args, &transform),
+            args.format,
+            quality(args),
+            args.avif_speed,
+        )
+        .map_err(|code| TilerError::WarpFailed {
+            tile: Tile {
+                zoom: max_zoom,
+                x: 0,
+                y: 0,
+            },
+            code,
+        })?;
+
+        let Some((sampled, average_size)) = sample else {
+            info!("Zoom {max_zoom}: no non-empty tiles sampled");
+
+            break;
+        };
+
+        info!("Zoom {max_zoom}: average tile size {average_size:.0} B of {sampled} sampled tiles");
+
+        if average_size < target_bytes as f64 || max_zoom == args.min_zoom {
+            break;
+        }
+
+        max_zoom -= 1;
+    }
+
+    info!("Using max zoom {max_zoom}");
+
+    Ok(max_zoom)
+}
+
+/// Number of rows read at once by `existing_tiles`
+const EXISTING_TILES_PAGE_SIZE: usize = 100_000;
+
+/// Returns tiles of `pending_set` present in the MBTiles file, reading it by pages.
+fn existing_tiles(
+    path: &Path,
+    tile_row_order: TileRowOrder,
+    pending_set: &HashSet<Tile>,
+) -> Result<HashSet<Tile>, TilerError> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let mut stmt = conn.prepare(
+        "SELECT zoom_level, tile_column, tile_row FROM tiles WHERE (zoom_level, tile_column, tile_row) > (?1, ?2, ?3) ORDER BY zoom_level, tile_column, tile_row LIMIT ?4",
+    )?;
+
+    let mut existing = HashSet::new();
+
+    // the page starts after the last row of the previous one
+    let mut last_key: (i64, i64, i64) = (-1, -1, -1);
+
+    loop {
+        let page = stmt
+            .query_map(
+                (last_key.0, last_key.1, last_key.2, EXISTING_TILES_PAGE_SIZE),
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )?
+            .collect::<Result<Vec<(u8, u32, u32)>, _>>()?;
+
+        let last = page.len() < EXISTING_TILES_PAGE_SIZE;
+
+        if let Some(&(zoom, x, row)) = page.last() {
+            last_key = (zoom.into(), x.into(), row.into());
+        }
+
+        existing.extend(
+            page.into_iter()
+                .map(|(zoom, x, row)| tile_row_order.tile(zoom, x, row))
+                .filter(|tile| pending_set.contains(tile)),
+        );
+
+        if last {
+            return Ok(existing);
+        }
+    }
+}
+
+/// Returns the highest zoom with inserted tiles and EPSG:3857 bounds of its tiles according to the limits.
+fn tiles_bounds(
+    limits: &HashMap<u8, Limits>,
+    tile_size: u16,
+    tile_row_order: TileRowOrder,
+) -> Option<(u8, [f64; 4])> {
+    let (&zoom, limits) = limits.iter().max_by_key(|(zoom, _)| **zoom)?;
+
+    // limits are of the stored rows
+    let tile = |x, row| tile_row_order.tile(zoom, x, row).bounds(tile_size);
+
+    let a = tile(limits.min_x, limits.min_y);
+
+    let b = tile(limits.max_x, limits.max_y);
+
+    Some((
+        zoom,
+        [
+            a.min_x.min(b.min_x),
+            a.min_y.min(b.min_y),
+            a.max_x.max(b.max_x),
+            a.max_y.max(b.max_y),
+        ],
+    ))
+}
+
+/// Resolves SRS of the source and its transformation to EPSG:3857. Returns the source SRS, the transformation and EPSG:3857 bounds.
+fn source_transform(
+    args: &Args,
+    source: &Source,
+    source_ds: &Dataset,
+) -> Result<(SpatialRef, Transform, [f64; 4]), TilerError> {
+    let source_srs = if let Some(source_srs) = source.srs.as_deref() {
+        SpatialRef::from_definition(source_srs)
+            .map_err(|e| TilerError::InvalidSpatialReference(e.to_string()))?
+    } else if let Some(code) = args.source_epsg {
+        SpatialRef::from_epsg(code).map_err(|_| {
+            TilerError::InvalidSpatialReference(format!("EPSG:{code} is not a known code"))
+        })?
+    } else if let Some(ref source_srs) = args.source_srs {
+        SpatialRef::from_definition(source_srs)
+            .map_err(|e| TilerError::InvalidSpatialReference(e.to_string()))?
+    } else {
+        source_ds.spatial_ref().map_err(|_| {
+            TilerError::InvalidSpatialReference(format!(
+                "{} has no CRS, specify --source-epsg or --source-srs",
+                source.path.display()
+            ))
+        })?
+    };
+
+    if args.transform_pipeline.is_none() && is_wgs84(&source_srs) {
+        warn!(
+            suggested_pipeline = WGS84_TO_WEB_MERCATOR_PIPELINE,
+            "Source CRS is geographic (EPSG:4326), tiles may be distorted. Use --transform-pipeline for best quality."
+        );
+    }
+
+    let target_srs = SpatialRef::from_epsg(3857)?;
+
+    let bbox = compute_bbox(source_ds);
+
+    let mut options = CoordTransformOptions::new()?;
+
+    let transform = if let Some(ref pipeline) = args.transform_pipeline {
+        options.set_coordinate_operation(pipeline, false)?;
+
+        Transform::Pipeline(pipeline.to_string())
+    } else {
+        Transform::Srs(source_srs.to_wkt()?, target_srs.to_wkt()?)
+    };
+
+    let coord_transform = CoordTransform::new_with_options(&source_srs, &target_srs, &options)?;
+
+    if (source.srs.is_some() || args.source_srs.is_some() || args.source_epsg.is_some())
+        && !args.skip_srs_validation
+    {
+        let mut x = [(bbox.min_x + bbox.max_x) / 2.0];
+        let mut y = [(bbox.min_y + bbox.max_y) / 2.0];
+
+        if coord_transform
+            .transform_coords(&mut x, &mut y, &mut [])
+            .is_err()
+            || !is_within_web_mercator(x[0], y[0])
+        {
+            warn!(
+                "The specified source SRS may be incorrect: test projection resulted in coordinates outside Web Mercator bounds."
+            );
+        }
+    }
+
+    let bounds =
+        coord_transform.transform_bounds(&[bbox.min_x, bbox.min_y, bbox.max_x, bbox.max_y], 21)?;
+
+    if !is_plausible_extent(&bounds) {
+        warn!(
+            "Source extent is suspiciously large ({}), check the source SRS.",
+            bounds.map(|c| format!("{c:.0}")).join(", ")
+        );
+    }
+
+    Ok((source_srs, transform, bounds))
+}
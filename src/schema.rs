@@ -1,12 +1,101 @@
-use rusqlite::{Connection, Error};
+use rusqlite::{Connection, Error, OptionalExtension};
+use std::collections::HashMap;
 
-use crate::args::Format;
+use crate::args::{AlphaStorage, Format, TileRowOrder};
+
+/// Metadata keys written by the tiler itself
+pub const RESERVED_METADATA_KEYS: [&str; 9] = [
+    "name",
+    "format",
+    "minzoom",
+    "maxzoom",
+    "bounds",
+    "center",
+    "limits",
+    "tile_row_order",
+    "tile_data_format",
+];
+
+/// Keys of `tile_data_format` which must match to continue from the file
+const TILE_DATA_FORMAT_DECODING_KEYS: [&str; 3] = ["format", "alpha", "compression"];
+
+/// Returns JSON description of how to decode tiles, stored as `tile_data_format` metadata.
+pub fn tile_data_format(
+    format: Format,
+    quality: u8,
+    alpha_storage: AlphaStorage,
+    alpha_compression_level: i32,
+    png_post_compress: bool,
+) -> String {
+    let mut value = serde_json::json!({ "format": format });
+
+    if !matches!(
+        format,
+        Format::PNG | Format::PNG8 | Format::TerrainRgb | Format::Terrarium
+    ) {
+        value["quality"] = quality.into();
+    }
+
+    value["alpha"] = match (format, alpha_storage) {
+        (_, AlphaStorage::None) | (Format::TerrainRgb | Format::Terrarium, _) => "none",
+        (Format::PNG | Format::PNG8, AlphaStorage::PerTile) => "png",
+        (Format::WebP, AlphaStorage::PerTile) => "webp",
+        (Format::AVIF, AlphaStorage::PerTile) => "avif",
+        (_, AlphaStorage::PerTile) => "zstd",
+    }
+    .into();
+
+    if value["alpha"] == "zstd" {
+        value["alpha_level"] = alpha_compression_level.into();
+    }
+
+    if png_post_compress {
+        value["compression"] = "zstd+png".into();
+    }
+
+    value.to_string()
+}
+
+/// Returns error message if tiles described by the `tile_data_format` values can't be decoded the same way.
+pub fn check_tile_data_format_compatibility(existing: &str, current: &str) -> Result<(), String> {
+    let existing: serde_json::Value =
+        serde_json::from_str(existing).map_err(|e| format!("invalid tile_data_format: {e}"))?;
+
+    let current: serde_json::Value =
+        serde_json::from_str(current).expect("tile_data_format should be valid JSON");
+
+    for key in TILE_DATA_FORMAT_DECODING_KEYS {
+        if existing.get(key) != current.get(key) {
+            return Err(format!(
+                "continue file has tile_data_format {key} {} but {} is requested",
+                existing.get(key).unwrap_or(&serde_json::Value::Null),
+                current.get(key).unwrap_or(&serde_json::Value::Null)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Metadata written to the created schema
+pub struct TilesetMetadata {
+    pub name: String,
+    pub description: Option<String>,
+    pub attribution: Option<String>,
+    /// WGS 84 bounds
+    pub bounds: [f64; 4],
+    /// JSON of the encoding parameters, see `tile_data_format`
+    pub tile_data_format: String,
+}
 
 pub fn create_schema(
     conn: &Connection,
+    min_zoom: u8,
     max_zoom: u8,
     format: Format,
-    bounds: [f64; 4],
+    tile_row_order: TileRowOrder,
+    dedup: bool,
+    metadata: &TilesetMetadata,
 ) -> Result<(), Error> {
     conn.execute(
         "CREATE TABLE metadata (
@@ -17,44 +106,68 @@ pub fn create_schema(
         (),
     )?;
 
-    conn.execute(
-        &format!(
-            "CREATE TABLE tiles (
+    let extra_columns = match format {
+        Format::JPEG => "tile_alpha BLOB NOT NULL,",
+        Format::PNG
+        | Format::PNG8
+        | Format::WebP
+        | Format::AVIF
+        | Format::TerrainRgb
+        | Format::Terrarium => "",
+        Format::Auto => "tile_alpha BLOB NOT NULL, tile_format TEXT NOT NULL,",
+    };
+
+    if dedup {
+        create_dedup_tables(conn, format, extra_columns)?;
+    } else {
+        conn.execute(
+            &format!(
+                "CREATE TABLE tiles (
           zoom_level INTEGER NOT NULL,
           tile_column INTEGER NOT NULL,
           tile_row INTEGER NOT NULL,
-          tile_data BLOB NOT NULL
-          {}
-        )",
-            match format {
-                Format::JPEG => ", tile_alpha BLOB NOT NULL",
-                Format::PNG => "",
-            }
-        ),
-        (),
-    )?;
+          tile_data BLOB NOT NULL,
+          {extra_columns}
+          UNIQUE(zoom_level, tile_column, tile_row)
+        )"
+            ),
+            (),
+        )?;
+    }
 
     conn.execute(
-        "CREATE UNIQUE INDEX idx_tiles ON tiles (zoom_level, tile_column, tile_row)",
-        (),
+        "INSERT INTO metadata (name, value) VALUES ('name', ?1)",
+        [&metadata.name],
     )?;
 
-    conn.execute(
-        "INSERT INTO metadata (name, value) VALUES ('name', 'Tiles')",
-        (),
-    )?;
+    if let Some(ref description) = metadata.description {
+        conn.execute(
+            "INSERT INTO metadata (name, value) VALUES ('description', ?1)",
+            [description],
+        )?;
+    }
+
+    if let Some(ref attribution) = metadata.attribution {
+        conn.execute(
+            "INSERT INTO metadata (name, value) VALUES ('attribution', ?1)",
+            [attribution],
+        )?;
+    }
 
     conn.execute(
         "INSERT INTO metadata (name, value) VALUES ('format', ?1)",
         [match format {
             Format::JPEG => "jpeg",
-            Format::PNG => "png",
+            Format::PNG | Format::PNG8 | Format::TerrainRgb | Format::Terrarium => "png",
+            Format::WebP => "webp",
+            Format::AVIF => "avif",
+            Format::Auto => "auto",
         }],
     )?;
 
     conn.execute(
-        "INSERT INTO metadata (name, value) VALUES ('minzoom', 0)",
-        (),
+        "INSERT INTO metadata (name, value) VALUES ('minzoom', ?1)",
+        [min_zoom],
     )?;
 
     conn.execute(
@@ -64,8 +177,142 @@ pub fn create_schema(
 
     conn.execute(
         "INSERT INTO metadata (name, value) VALUES ('bounds', ?1)",
-        [bounds.map(|c| format!("{}", c)).join(",")],
+        [metadata.bounds.map(|c| format!("{}", c)).join(",")],
+    )?;
+
+    conn.execute(
+        "INSERT INTO metadata (name, value) VALUES ('tile_row_order', ?1)",
+        [tile_row_order.as_str()],
+    )?;
+
+    conn.execute(
+        "INSERT INTO metadata (name, value) VALUES ('tile_data_format', ?1)",
+        [&metadata.tile_data_format],
+    )?;
+
+    Ok(())
+}
+
+/// Creates the de-duplicated layout: tile images are stored once in `image_data` keyed by SHA-256
+/// hex of the data and referenced from `map`. The `tiles` view keeps the layout readable as the
+/// plain one.
+fn create_dedup_tables(
+    conn: &Connection,
+    format: Format,
+    extra_columns: &str,
+) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE image_data (
+          tile_id TEXT NOT NULL,
+          tile_data BLOB NOT NULL,
+          UNIQUE(tile_id)
+        )",
+        (),
+    )?;
+
+    conn.execute(
+        &format!(
+            "CREATE TABLE map (
+          zoom_level INTEGER NOT NULL,
+          tile_column INTEGER NOT NULL,
+          tile_row INTEGER NOT NULL,
+          tile_id TEXT NOT NULL,
+          {extra_columns}
+          UNIQUE(zoom_level, tile_column, tile_row)
+        )"
+        ),
+        (),
+    )?;
+
+    conn.execute(
+        &format!(
+            "CREATE VIEW tiles AS SELECT zoom_level, tile_column, tile_row, tile_data{}
+          FROM map JOIN image_data USING (tile_id)",
+            match format {
+                Format::JPEG => ", tile_alpha",
+                Format::PNG
+                | Format::PNG8
+                | Format::WebP
+                | Format::AVIF
+                | Format::TerrainRgb
+                | Format::Terrarium => "",
+                Format::Auto => ", tile_alpha, tile_format",
+            }
+        ),
+        (),
     )?;
 
     Ok(())
 }
+
+pub fn create_checksums_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS checksums (
+          zoom_level INTEGER NOT NULL,
+          tile_column INTEGER NOT NULL,
+          tile_row INTEGER NOT NULL,
+          sha256_hex TEXT NOT NULL
+        )",
+        (),
+    )?;
+
+    conn.execute(
+        "CREATE UNIQUE INDEX IF NOT EXISTS idx_checksums ON checksums (zoom_level, tile_column, tile_row)",
+        (),
+    )?;
+
+    Ok(())
+}
+
+pub fn create_dictionary_table(conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS zstd_dictionary (data BLOB NOT NULL)",
+        (),
+    )?;
+
+    Ok(())
+}
+
+/// Returns zstd dictionary used to compress tile data or `None` if tile data is not zstd-compressed.
+pub fn read_dictionary(conn: &Connection) -> Result<Option<Vec<u8>>, Error> {
+    if read_metadata(conn, "tile_data_compression")?.as_deref() != Some("zstd+png") {
+        return Ok(None);
+    }
+
+    if !has_table(conn, "zstd_dictionary")? {
+        return Ok(Some(vec![]));
+    }
+
+    Ok(Some(
+        conn.query_row("SELECT data FROM zstd_dictionary", (), |row| row.get(0))
+            .optional()?
+            .unwrap_or_default(),
+    ))
+}
+
+pub fn has_table(conn: &Connection, name: &str) -> Result<bool, Error> {
+    conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        [name],
+        |row| row.get(0),
+    )
+}
+
+pub fn read_metadata(conn: &Connection, name: &str) -> Result<Option<String>, Error> {
+    conn.query_row(
+        "SELECT value FROM metadata WHERE name = ?1",
+        [name],
+        |row| row.get(0),
+    )
+    .optional()
+}
+
+pub fn insert_metadata(conn: &Connection, metadata: &HashMap<String, String>) -> Result<(), Error> {
+    let mut stmt = conn.prepare("INSERT OR REPLACE INTO metadata (name, value) VALUES (?1, ?2)")?;
+
+    for (name, value) in metadata {
+        stmt.execute((name, value))?;
+    }
+
+    Ok(())
+}
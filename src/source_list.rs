@@ -0,0 +1,122 @@
+use crate::palette::{is_palette, palette_vrt};
+use gdal::{Dataset, DatasetOptions, errors::GdalError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+pub struct Source {
+    pub path: PathBuf,
+    pub srs: Option<String>,
+}
+
+/// Returns true for a GDAL virtual file system path like `/vsis3/...` or `/vsicurl/...`, which
+/// can't be accessed through the local filesystem.
+pub fn is_vsi_path(path: &Path) -> bool {
+    path.to_string_lossy().starts_with("/vsi")
+}
+
+/// Opens the source dataset at the overview level, 0 being the full resolution, 1 the first overview and so on.
+///
+/// Palette-indexed source is opened as VRT expanding the palette to RGBA, with pixels of
+/// `palette_nodata_index` transparent.
+pub fn open_source(
+    path: &Path,
+    overview_level: u8,
+    palette_nodata_index: Option<u8>,
+) -> Result<Dataset, GdalError> {
+    let source_ds = if overview_level == 0 {
+        Dataset::open(path)?
+    } else {
+        Dataset::open_ex(
+            path,
+            DatasetOptions {
+                open_options: Some(&[&format!("OVERVIEW_LEVEL={}", overview_level - 1)]),
+                ..Default::default()
+            },
+        )?
+    };
+
+    if !is_palette(&source_ds) {
+        return Ok(source_ds);
+    }
+
+    Dataset::open(palette_vrt(
+        &source_ds,
+        path,
+        overview_level,
+        palette_nodata_index,
+    ))
+}
+
+/// Returns files matching the glob pattern sorted alphabetically. If `recursive` is set, file name
+/// of the pattern is matched also in subdirectories.
+pub fn expand_source_file_pattern(pattern: &str, recursive: bool) -> Result<Vec<PathBuf>, String> {
+    let pattern = if recursive {
+        let path = Path::new(pattern);
+
+        path.parent()
+            .unwrap_or(Path::new(""))
+            .join("**")
+            .join(path.file_name().ok_or("Pattern has no file name")?)
+            .to_string_lossy()
+            .into_owned()
+    } else {
+        pattern.to_string()
+    };
+
+    let mut paths = glob::glob(&pattern)
+        .map_err(|e| format!("Invalid pattern: {e}"))?
+        .filter_map(|entry| entry.ok().filter(|path| path.is_file()))
+        .collect::<Vec<_>>();
+
+    if paths.is_empty() {
+        return Err(format!("No files match pattern \"{pattern}\""));
+    }
+
+    paths.sort();
+
+    Ok(paths)
+}
+
+/// Parses list of source files, one per line. Line can be just a path or `path=<path> srs=<srs>`.
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_source_file_list(file_path: &Path) -> Result<Vec<Source>, String> {
+    let content = fs::read_to_string(file_path).map_err(|e| format!("Failed to read file: {e}"))?;
+
+    let mut sources = Vec::new();
+
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut path = None;
+
+        let mut srs = None;
+
+        for token in line.split_whitespace() {
+            match token.split_once('=') {
+                Some(("path", value)) => path = Some(PathBuf::from(value)),
+                Some(("srs", value)) => srs = Some(value.to_string()),
+                Some((key, _)) => return Err(format!("Unknown key \"{key}\" on line {}", i + 1)),
+                None if path.is_none() => path = Some(PathBuf::from(token)),
+                None => return Err(format!("Unexpected \"{token}\" on line {}", i + 1)),
+            }
+        }
+
+        let Some(path) = path else {
+            return Err(format!("Missing path on line {}", i + 1));
+        };
+
+        sources.push(Source { path, srs });
+    }
+
+    if sources.is_empty() {
+        return Err("No source files listed".into());
+    }
+
+    Ok(sources)
+}
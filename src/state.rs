@@ -1,4 +1,5 @@
-use std::collections::HashSet;
+use crate::tile_filter::TileFilter;
+use std::collections::{HashMap, HashSet};
 use tilemath::Tile;
 
 pub struct State {
@@ -6,29 +7,48 @@ pub struct State {
     processed_set: HashSet<Tile>, // finished
     waiting_set: HashSet<Tile>,
     pending_vec: Vec<Tile>,
+    pending_by_zoom: HashMap<u8, usize>,
+    min_zoom: u8,
     max_zoom: u8,
     zoom_offset: u8,
+    tile_filter: Option<TileFilter>,
 }
 
 impl State {
     pub fn new(
         pending_vec: Vec<Tile>,
         pending_set: HashSet<Tile>,
+        processed_set: HashSet<Tile>,
+        min_zoom: u8,
         max_zoom: u8,
         zoom_offset: u8,
+        tile_filter: Option<TileFilter>,
     ) -> Self {
+        let mut pending_by_zoom = HashMap::new();
+
+        for tile in &pending_set {
+            *pending_by_zoom.entry(tile.zoom).or_insert(0) += 1;
+        }
+
         Self {
             pending_set,
-            processed_set: HashSet::new(),
+            processed_set,
             waiting_set: HashSet::new(),
             pending_vec,
+            pending_by_zoom,
+            min_zoom,
             max_zoom,
             zoom_offset,
+            tile_filter,
         }
     }
 
     pub fn processed(&mut self, tile: Tile) {
-        self.pending_set.remove(&tile);
+        if self.pending_set.remove(&tile)
+            && let Some(count) = self.pending_by_zoom.get_mut(&tile.zoom)
+        {
+            *count -= 1;
+        }
         self.waiting_set.remove(&tile);
         self.processed_set.insert(tile);
 
@@ -36,10 +56,23 @@ impl State {
             return;
         };
 
+        if parent.zoom < self.min_zoom {
+            return;
+        }
+
         if self.waiting_set.contains(&parent) || self.processed_set.contains(&parent) {
             return;
         }
 
+        // neither the parent nor its ancestors are generated
+        if self
+            .tile_filter
+            .as_ref()
+            .is_some_and(|tile_filter| !tile_filter.matches_or_ancestor(&parent, self.min_zoom))
+        {
+            return;
+        }
+
         let children = parent.children();
 
         if children.iter().all(|tile| !self.pending_set.contains(tile)) {
@@ -48,7 +81,18 @@ impl State {
         }
     }
 
-    pub fn next(&mut self) -> Option<Vec<Tile>> {
+    /// Returns true if the tile is not yet processed.
+    pub fn is_pending(&self, tile: &Tile) -> bool {
+        self.pending_set.contains(tile)
+    }
+
+    /// Returns number of not yet processed tiles by zoom.
+    pub fn tiles_by_zoom(&self) -> HashMap<u8, usize> {
+        self.pending_by_zoom.clone()
+    }
+
+    /// Returns the next task, a lower zoom tile or max zoom tiles of the same megatile.
+    pub fn next_task(&mut self) -> Option<Vec<Tile>> {
         let mut tiles = Vec::with_capacity(1);
 
         let mut key: Option<Tile> = None;
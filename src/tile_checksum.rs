@@ -0,0 +1,80 @@
+use crate::error::TilerError;
+use rusqlite::{Connection, OpenFlags};
+use std::path::Path;
+
+/// Prints tiles added, removed and changed in `other` compared to `file` by their stored checksums.
+pub fn diff_tiles(file: &Path, other: &Path) -> Result<(), TilerError> {
+    for path in [file, other] {
+        if !path.exists() {
+            return Err(TilerError::SourceNotFound(path.to_path_buf()));
+        }
+    }
+
+    let conn = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    conn.execute(
+        "ATTACH DATABASE ?1 AS other",
+        [other.to_string_lossy().as_ref()],
+    )?;
+
+    for (path, schema) in [(file, "main"), (other, "other")] {
+        let exists: bool = conn.query_row(
+            &format!(
+                "SELECT EXISTS (SELECT 1 FROM {schema}.sqlite_master WHERE type = 'table' AND name = 'checksums')"
+            ),
+            (),
+            |row| row.get(0),
+        )?;
+
+        if !exists {
+            return Err(TilerError::InvalidArgument(format!(
+                "{} has no tile checksums, generate it with --store-checksums",
+                path.display()
+            )));
+        }
+    }
+
+    let queries = [
+        (
+            "added",
+            "SELECT b.zoom_level, b.tile_column, b.tile_row FROM other.checksums b
+              LEFT JOIN main.checksums a USING (zoom_level, tile_column, tile_row)
+              WHERE a.sha256_hex IS NULL",
+        ),
+        (
+            "removed",
+            "SELECT a.zoom_level, a.tile_column, a.tile_row FROM main.checksums a
+              LEFT JOIN other.checksums b USING (zoom_level, tile_column, tile_row)
+              WHERE b.sha256_hex IS NULL",
+        ),
+        (
+            "changed",
+            "SELECT a.zoom_level, a.tile_column, a.tile_row FROM main.checksums a
+              JOIN other.checksums b USING (zoom_level, tile_column, tile_row)
+              WHERE a.sha256_hex <> b.sha256_hex",
+        ),
+    ];
+
+    for (change, query) in queries {
+        let mut stmt = conn.prepare(query)?;
+
+        let mut rows = stmt.query(())?;
+
+        let mut count = 0;
+
+        while let Some(row) = rows.next()? {
+            println!(
+                "{change} {}/{}/{}",
+                row.get::<_, u8>(0)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, u32>(2)?
+            );
+
+            count += 1;
+        }
+
+        eprintln!("{count} tile(s) {change}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,396 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    iter,
+};
+use tilemath::Tile;
+
+#[derive(Debug)]
+pub struct ParseError(String);
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid tile filter expression: {}", self.0)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Number(i64),
+    Ident(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Var {
+    Zoom,
+    X,
+    Y,
+    ReversedY,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Var(Var),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+}
+
+/// Tile filter parsed from expression like `zoom >= 14 AND (x % 2 == 0 OR y % 3 == 0)`.
+///
+/// Supports variables `zoom`, `x`, `y` and `reversed_y`, integer comparisons, `AND`, `OR`, `NOT`,
+/// arithmetic `%`, `/`, `*`, `+`, `-` and parentheses.
+#[derive(Clone, Debug)]
+pub struct TileFilter(Expr);
+
+const OPS: [&str; 13] = [
+    "==", "!=", "<=", ">=", "<", ">", "+", "-", "*", "/", "%", "AND", "OR",
+];
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ParseError> {
+    let mut tokens = Vec::new();
+
+    let mut chars = s.char_indices().peekable();
+
+    while let Some(&(i, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '(' {
+            chars.next();
+
+            tokens.push(Token::LParen);
+        } else if c == ')' {
+            chars.next();
+
+            tokens.push(Token::RParen);
+        } else if c.is_ascii_digit() {
+            let mut end = i;
+
+            while let Some(&(j, c)) = chars.peek()
+                && c.is_ascii_digit()
+            {
+                end = j + 1;
+
+                chars.next();
+            }
+
+            tokens.push(Token::Number(s[i..end].parse().map_err(|e| {
+                ParseError(format!("invalid number {}: {e}", &s[i..end]))
+            })?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = i;
+
+            while let Some(&(j, c)) = chars.peek()
+                && (c.is_ascii_alphanumeric() || c == '_')
+            {
+                end = j + 1;
+
+                chars.next();
+            }
+
+            let word = &s[i..end];
+
+            match word.to_ascii_uppercase().as_str() {
+                "AND" => tokens.push(Token::Op("AND")),
+                "OR" => tokens.push(Token::Op("OR")),
+                "NOT" => tokens.push(Token::Op("NOT")),
+                _ => tokens.push(Token::Ident(word.to_string())),
+            }
+        } else {
+            let op = OPS
+                .iter()
+                .find(|op| s[i..].starts_with(**op))
+                .ok_or_else(|| ParseError(format!("unexpected character '{c}' at {i}")))?;
+
+            for _ in 0..op.len() {
+                chars.next();
+            }
+
+            tokens.push(Token::Op(op));
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek_op(&self, ops: &[&'static str]) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) if ops.contains(op) => Some(op),
+            _ => None,
+        }
+    }
+
+    /// Parses left associative binary operators of the same precedence.
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        operand: fn(&mut Self) -> Result<Expr, ParseError>,
+    ) -> Result<Expr, ParseError> {
+        let mut left = operand(self)?;
+
+        while let Some(op) = self.peek_op(ops) {
+            self.pos += 1;
+
+            left = Expr::Binary(op, Box::new(left), Box::new(operand(self)?));
+        }
+
+        Ok(left)
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&["OR"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&["AND"], Self::not)
+    }
+
+    fn not(&mut self) -> Result<Expr, ParseError> {
+        if self.peek_op(&["NOT"]).is_some() {
+            self.pos += 1;
+
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ParseError> {
+        let left = self.sum()?;
+
+        if let Some(op) = self.peek_op(&["==", "!=", "<=", ">=", "<", ">"]) {
+            self.pos += 1;
+
+            return Ok(Expr::Binary(op, Box::new(left), Box::new(self.sum()?)));
+        }
+
+        Ok(left)
+    }
+
+    fn sum(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&["+", "-"], Self::product)
+    }
+
+    fn product(&mut self) -> Result<Expr, ParseError> {
+        self.binary(&["*", "/", "%"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.peek_op(&["-"]).is_some() {
+            self.pos += 1;
+
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError("unexpected end of expression".into()))?;
+
+        self.pos += 1;
+
+        match token {
+            Token::Number(n) => Ok(Expr::Number(n)),
+            Token::Ident(name) => match name.as_str() {
+                "zoom" => Ok(Expr::Var(Var::Zoom)),
+                "x" => Ok(Expr::Var(Var::X)),
+                "y" => Ok(Expr::Var(Var::Y)),
+                "reversed_y" => Ok(Expr::Var(Var::ReversedY)),
+                _ => Err(ParseError(format!("unknown variable {name}"))),
+            },
+            Token::LParen => {
+                let expr = self.or()?;
+
+                if self.tokens.get(self.pos) != Some(&Token::RParen) {
+                    return Err(ParseError("missing closing parenthesis".into()));
+                }
+
+                self.pos += 1;
+
+                Ok(expr)
+            }
+            token => Err(ParseError(format!("unexpected token {token:?}"))),
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression, booleans are represented as 0 and 1 and division by zero results in 0.
+    fn eval(&self, tile: &Tile) -> i64 {
+        match self {
+            Self::Number(n) => *n,
+            Self::Var(Var::Zoom) => tile.zoom.into(),
+            Self::Var(Var::X) => tile.x.into(),
+            Self::Var(Var::Y) => tile.y.into(),
+            Self::Var(Var::ReversedY) => tile.reversed_y().into(),
+            Self::Not(expr) => (expr.eval(tile) == 0).into(),
+            Self::Neg(expr) => expr.eval(tile).wrapping_neg(),
+            Self::Binary(op, left, right) => {
+                let left = left.eval(tile);
+
+                // short-circuit
+                match *op {
+                    "AND" if left == 0 => return 0,
+                    "OR" if left != 0 => return 1,
+                    _ => {}
+                }
+
+                let right = right.eval(tile);
+
+                match *op {
+                    "AND" | "OR" => (right != 0).into(),
+                    "==" => (left == right).into(),
+                    "!=" => (left != right).into(),
+                    "<" => (left < right).into(),
+                    "<=" => (left <= right).into(),
+                    ">" => (left > right).into(),
+                    ">=" => (left >= right).into(),
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left.checked_div(right).unwrap_or(0),
+                    "%" => left.checked_rem(right).unwrap_or(0),
+                    _ => unreachable!("unknown operator {op}"),
+                }
+            }
+        }
+    }
+}
+
+impl TileFilter {
+    pub fn from_expr(s: &str) -> Result<Self, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        };
+
+        let expr = parser.or()?;
+
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            return Err(ParseError(format!("unexpected token {token:?}")));
+        }
+
+        Ok(Self(expr))
+    }
+
+    /// Returns true if the expression evaluates to non-zero for the tile.
+    pub fn matches(&self, tile: &Tile) -> bool {
+        self.0.eval(tile) != 0
+    }
+
+    /// Returns true if the tile or any of its ancestors down to `min_zoom` matches, ie. the tile is
+    /// needed to compose a matching tile.
+    pub fn matches_or_ancestor(&self, tile: &Tile, min_zoom: u8) -> bool {
+        iter::successors(Some(*tile), Tile::parent)
+            .take_while(|tile| tile.zoom >= min_zoom)
+            .any(|tile| self.matches(&tile))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: Tile = Tile {
+        zoom: 3,
+        x: 5,
+        y: 2,
+    };
+
+    fn eval(expr: &str) -> bool {
+        TileFilter::from_expr(expr)
+            .expect("expression should be parsed")
+            .matches(&TILE)
+    }
+
+    #[test]
+    fn variables() {
+        assert!(eval("zoom == 3"));
+        assert!(eval("x == 5"));
+        assert!(eval("y == 2"));
+        assert!(eval("reversed_y == 5"));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(eval("x == 5") && !eval("x == 4"));
+        assert!(eval("x != 4") && !eval("x != 5"));
+        assert!(eval("x < 6") && !eval("x < 5"));
+        assert!(eval("x <= 5") && !eval("x <= 4"));
+        assert!(eval("x > 4") && !eval("x > 5"));
+        assert!(eval("x >= 5") && !eval("x >= 6"));
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert!(eval("x + 2 == 7"));
+        assert!(eval("x - 7 == -2"));
+        assert!(eval("x * 3 == 15"));
+        assert!(eval("x / 2 == 2"));
+        assert!(eval("x % 3 == 2"));
+        assert!(eval("-x == -5"));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert!(eval("x / 0 == 0"));
+        assert!(eval("x % 0 == 0"));
+    }
+
+    #[test]
+    fn logical() {
+        assert!(eval("x == 5 AND y == 2") && !eval("x == 5 AND y == 3"));
+        assert!(eval("x == 4 OR y == 2") && !eval("x == 4 OR y == 3"));
+        assert!(eval("NOT x == 4") && !eval("NOT x == 5"));
+        assert!(eval("x == 5 and y == 2 or zoom == 0"));
+    }
+
+    #[test]
+    fn precedence() {
+        assert!(eval("x + 2 * 3 == 11"));
+        assert!(eval("(x + 2) * 3 == 21"));
+        assert!(eval("x - 2 - 1 == 2"));
+        assert!(eval("x / 2 * 2 == 4"));
+        assert!(eval("x == 4 AND y == 3 OR zoom == 3"));
+        assert!(!eval("x == 4 AND (y == 3 OR zoom == 3)"));
+        assert!(eval("NOT x == 4 AND y == 2"));
+        assert!(eval("x == 4 OR NOT y == 3 AND zoom == 3"));
+    }
+
+    #[test]
+    fn errors() {
+        for expr in [
+            "",
+            "x ==",
+            "(x == 5",
+            "x == 5)",
+            "foo == 1",
+            "x == 5 $",
+            "x 5",
+            "99999999999999999999 == 1",
+        ] {
+            assert!(TileFilter::from_expr(expr).is_err(), "{expr} should fail");
+        }
+    }
+
+    #[test]
+    fn ancestors() {
+        let filter = TileFilter::from_expr("zoom == 1").expect("expression should be parsed");
+
+        assert!(filter.matches_or_ancestor(&TILE, 0));
+        assert!(!filter.matches_or_ancestor(&TILE, 2));
+    }
+}
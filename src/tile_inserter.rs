@@ -1,62 +1,527 @@
 use crate::{
-    args::Format,
-    schema::create_schema,
+    args::{Format, TileRowOrder},
+    checksum::{sha256_hex, tile_checksum},
+    schema::{
+        TilesetMetadata, create_checksums_table, create_dictionary_table, create_schema, has_table,
+    },
+    tile_path::TilePath,
     time_track::{Metric, StatsMsg},
+    world_file::write_world_file,
 };
-use rusqlite::Connection;
+use rusqlite::{Connection, OptionalExtension};
 use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    mem,
     path::Path,
     sync::mpsc::{Sender, SyncSender, sync_channel},
     thread::{self, JoinHandle},
     time::Instant,
 };
 use tilemath::Tile;
+use tracing::warn;
+use zstd::bulk::Compressor;
+
+/// Writer of `zoom/x/y` lines of the inserted tiles
+pub struct TileListWriter {
+    writer: BufWriter<File>,
+    flush_interval: usize,
+    count: usize,
+}
+
+impl TileListWriter {
+    /// Creates the file for writing.
+    pub fn open(path: &Path, flush_interval: usize) -> io::Result<Self> {
+        Ok(Self {
+            writer: BufWriter::new(File::create(path)?),
+            flush_interval,
+            count: 0,
+        })
+    }
+
+    /// Writes the tile, flushing every `flush_interval` tiles.
+    fn write(&mut self, tile: &Tile) -> io::Result<()> {
+        writeln!(self.writer, "{tile}")?;
+
+        self.count += 1;
+
+        if self.count.is_multiple_of(self.flush_interval) {
+            self.writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How to handle inserting a tile which already exists
+#[derive(Clone, Copy, Debug)]
+pub enum InsertMode {
+    /// Fail; a duplicate tile indicates a bug in the tiling logic
+    Insert,
+    IgnoreOnConflict,
+    ReplaceOnConflict,
+}
+
+/// Number of PNG tiles to train the zstd dictionary on
+const DICTIONARY_SAMPLES: usize = 1000;
+
+/// Max size of the trained zstd dictionary
+const DICTIONARY_SIZE: usize = 112_640;
+
+/// Returns true if the encoded tile data is a JPEG image (starts with the SOI marker)
+pub fn is_jpeg(data: &[u8]) -> bool {
+    data.starts_with(&[0xFF, 0xD8])
+}
+
+fn train_dictionary(conn: &Connection, samples: &[TileMsg]) -> Vec<u8> {
+    let samples: Vec<_> = samples
+        .iter()
+        .map(|msg| &msg.1)
+        .filter(|data| !data.is_empty() && !is_jpeg(data))
+        .collect();
+
+    let dictionary = zstd::dict::from_samples(&samples, DICTIONARY_SIZE).unwrap_or_else(|e| {
+        warn!("Error training zstd dictionary, compressing without it: {e}");
+
+        vec![]
+    });
+
+    conn.execute("DELETE FROM zstd_dictionary", ())
+        .expect("Dictionary should be deleted");
+
+    conn.execute(
+        "INSERT INTO zstd_dictionary (data) VALUES (?1)",
+        [&dictionary],
+    )
+    .expect("Dictionary should be inserted");
+
+    dictionary
+}
+
+/// Configuration of storing the tiles to MBTiles
+pub struct InserterConfig {
+    /// Zoom range of the schema to create, `None` to continue the existing file
+    pub zoom_range: Option<(u8, u8)>,
+    /// Number of the threads producing the tiles, sizes the channel
+    pub num_threads: u16,
+    pub format: Format,
+    pub store_checksums: bool,
+    pub insert_mode: InsertMode,
+    pub png_post_compress: bool,
+    pub tile_row_order: TileRowOrder,
+    pub dedup: bool,
+    /// Number of tiles inserted in one transaction
+    pub batch_size: usize,
+    /// Metadata of the created schema
+    pub metadata: TilesetMetadata,
+}
+
+/// Configuration of writing the tiles to the directory
+pub struct DirectoryConfig {
+    /// Number of the threads producing the tiles, sizes the channel
+    pub num_threads: u16,
+    pub ext: &'static str,
+    pub insert_mode: InsertMode,
+    pub tile_row_order: TileRowOrder,
+    /// Tile size of the world files to write next to the tiles, `None` for none
+    pub world_file_tile_size: Option<u16>,
+}
+
+/// Tile with its data and alpha sent to the inserter
+pub type TileMsg = (Tile, Vec<u8>, Vec<u8>);
+
+/// Thread storing the tiles and sender of the tiles to it
+pub type Inserter = (JoinHandle<()>, SyncSender<TileMsg>);
 
 pub fn new(
     target_file: &Path,
-    max_zoom: Option<u8>,
-    num_threads: u16,
+    config: InserterConfig,
     stats_tx: Sender<StatsMsg>,
-    format: Format,
-    bounds: [f64; 4],
-) -> rusqlite::Result<(JoinHandle<()>, SyncSender<(Tile, Vec<u8>, Vec<u8>)>)> {
-    let (data_tx, data_rx) = sync_channel::<(Tile, Vec<u8>, Vec<u8>)>(num_threads as usize * 16);
+    mut tile_list: Option<TileListWriter>,
+    committed_tx: Option<Sender<Vec<Tile>>>,
+) -> rusqlite::Result<Inserter> {
+    let InserterConfig {
+        zoom_range,
+        num_threads,
+        format,
+        store_checksums,
+        insert_mode,
+        png_post_compress,
+        tile_row_order,
+        dedup,
+        batch_size,
+        metadata,
+    } = config;
+
+    let (data_tx, data_rx) = sync_channel::<TileMsg>(num_threads as usize * 16);
 
     let conn = Connection::open(target_file)?;
 
-    if let Some(max_zoom) = max_zoom {
-        create_schema(&conn, max_zoom, format, bounds)?;
+    if let Some((min_zoom, max_zoom)) = zoom_range {
+        create_schema(
+            &conn,
+            min_zoom,
+            max_zoom,
+            format,
+            tile_row_order,
+            dedup,
+            &metadata,
+        )?;
     }
 
+    // keep the layout of the continued file
+    let dedup = if zoom_range.is_some() {
+        dedup
+    } else {
+        has_table(&conn, "image_data")?
+    };
+
+    if store_checksums {
+        create_checksums_table(&conn)?;
+    }
+
+    let dictionary = if png_post_compress {
+        create_dictionary_table(&conn)?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO metadata (name, value) VALUES ('tile_data_compression', 'zstd+png')",
+            (),
+        )?;
+
+        // reuse the dictionary when continuing
+        conn.query_row("SELECT data FROM zstd_dictionary", (), |row| {
+            row.get::<_, Vec<u8>>(0)
+        })
+        .optional()?
+    } else {
+        None
+    };
+
     conn.pragma_update(None, "synchronous", "OFF")?;
 
     conn.pragma_update(None, "journal_mode", "WAL")?;
 
     let insert_thread = thread::spawn(move || {
         let mut stmt = conn
-            .prepare(match format {
-                Format::JPEG => concat!(
-                    "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data, tile_alpha) ",
-                    "VALUES (?1, ?2, ?3, ?4, ?5)"
-                ),
-                Format::PNG => concat!(
-                    "INSERT INTO tiles (zoom_level, tile_column, tile_row, tile_data) ",
+            .prepare(&format!(
+                "{} {}",
+                match insert_mode {
+                    InsertMode::Insert => "INSERT",
+                    InsertMode::IgnoreOnConflict => "INSERT OR IGNORE",
+                    InsertMode::ReplaceOnConflict => "INSERT OR REPLACE",
+                },
+                match (format, dedup) {
+                    (Format::JPEG, false) => concat!(
+                        "INTO tiles (zoom_level, tile_column, tile_row, tile_data, tile_alpha) ",
+                        "VALUES (?1, ?2, ?3, ?4, ?5)"
+                    ),
+                    (Format::JPEG, true) => concat!(
+                        "INTO map (zoom_level, tile_column, tile_row, tile_id, tile_alpha) ",
+                        "VALUES (?1, ?2, ?3, ?4, ?5)"
+                    ),
+                    (
+                        Format::PNG
+                        | Format::PNG8
+                        | Format::WebP
+                        | Format::AVIF
+                        | Format::TerrainRgb
+                        | Format::Terrarium,
+                        false,
+                    ) => concat!(
+                        "INTO tiles (zoom_level, tile_column, tile_row, tile_data) ",
+                        "VALUES (?1, ?2, ?3, ?4)"
+                    ),
+                    (
+                        Format::PNG
+                        | Format::PNG8
+                        | Format::WebP
+                        | Format::AVIF
+                        | Format::TerrainRgb
+                        | Format::Terrarium,
+                        true,
+                    ) => concat!(
+                        "INTO map (zoom_level, tile_column, tile_row, tile_id) ",
+                        "VALUES (?1, ?2, ?3, ?4)"
+                    ),
+                    (Format::Auto, false) => concat!(
+                        "INTO tiles (zoom_level, tile_column, tile_row, tile_data, tile_alpha, tile_format) ",
+                        "VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+                    ),
+                    (Format::Auto, true) => concat!(
+                        "INTO map (zoom_level, tile_column, tile_row, tile_id, tile_alpha, tile_format) ",
+                        "VALUES (?1, ?2, ?3, ?4, ?5, ?6)"
+                    ),
+                }
+            ))
+            .expect("Insert statement should be prepared");
+
+        let mut image_stmts = dedup
+            .then(|| {
+                Ok::<_, rusqlite::Error>((
+                    conn.prepare("SELECT 1 FROM image_data WHERE tile_id = ?1")?,
+                    conn.prepare("INSERT INTO image_data (tile_id, tile_data) VALUES (?1, ?2)")?,
+                ))
+            })
+            .transpose()
+            .expect("Image data statements should be prepared");
+
+        let mut checksum_stmt = store_checksums
+            .then(|| {
+                conn.prepare(concat!(
+                    "INSERT OR REPLACE INTO checksums (zoom_level, tile_column, tile_row, sha256_hex) ",
                     "VALUES (?1, ?2, ?3, ?4)"
-                ),
+                ))
             })
-            .expect("Insert statement should be prepared");
+            .transpose()
+            .expect("Checksum insert statement should be prepared");
 
-        for msg in data_rx {
+        let mut compressor = dictionary.map(|dictionary| {
+            Compressor::with_dictionary(0, &dictionary).expect("zstd compressor should be created")
+        });
+
+        let mut samples = Vec::new();
+
+        let mut inserted = 0;
+
+        // tiles of the current transaction
+        let mut batch = Vec::new();
+
+        let mut insert = |msg: TileMsg, compressor: Option<&mut Compressor>| {
             let instant = Instant::now();
 
-            match format {
-                Format::JPEG => {
-                    stmt.execute((msg.0.zoom, msg.0.x, msg.0.reversed_y(), msg.1, msg.2))
+            if inserted % batch_size == 0 {
+                conn.execute("BEGIN", ())
+                    .expect("Transaction should be started");
+            }
+
+            let is_empty = msg.1.is_empty();
+
+            let tile_format = if is_empty {
+                ""
+            } else if is_jpeg(&msg.1) {
+                "jpeg"
+            } else {
+                "png"
+            };
+
+            let data = match compressor {
+                Some(compressor) if tile_format == "png" => compressor
+                    .compress(&msg.1)
+                    .expect("Tile data should be zstd compressed"),
+                _ => msg.1,
+            };
+
+            // alpha is stored only in the JPEG tiles
+            let alpha: &[u8] = match format {
+                Format::JPEG | Format::Auto => &msg.2,
+                Format::PNG
+                | Format::PNG8
+                | Format::WebP
+                | Format::AVIF
+                | Format::TerrainRgb
+                | Format::Terrarium => &[],
+            };
+
+            let checksum = checksum_stmt.as_ref().map(|_| tile_checksum(&data, alpha));
+
+            let row = tile_row_order.row(&msg.0);
+
+            // with de-duplication the tile references the image data by its hash
+            let data: rusqlite::types::Value = match image_stmts {
+                Some((ref mut exists_stmt, ref mut insert_image_stmt)) => {
+                    let tile_id = sha256_hex(&data);
+
+                    if !exists_stmt
+                        .exists([&tile_id])
+                        .expect("Image data existence should be checked")
+                    {
+                        insert_image_stmt
+                            .execute((&tile_id, data))
+                            .expect("Image data should be inserted");
+                    }
+
+                    tile_id.into()
                 }
-                Format::PNG => stmt.execute((msg.0.zoom, msg.0.x, msg.0.reversed_y(), msg.1)),
+                None => data.into(),
+            };
+
+            match format {
+                Format::JPEG => stmt.execute((msg.0.zoom, msg.0.x, row, data, msg.2)),
+                Format::PNG
+                | Format::PNG8
+                | Format::WebP
+                | Format::AVIF
+                | Format::TerrainRgb
+                | Format::Terrarium => stmt.execute((msg.0.zoom, msg.0.x, row, data)),
+                Format::Auto => stmt.execute((msg.0.zoom, msg.0.x, row, data, msg.2, tile_format)),
             }
             .expect("Tile should be inserted");
 
+            if let (Some(checksum_stmt), Some(checksum)) = (&mut checksum_stmt, checksum) {
+                checksum_stmt
+                    .execute((msg.0.zoom, msg.0.x, row, checksum))
+                    .expect("Checksum should be inserted");
+            }
+
+            // empty placeholders are stored only for resuming
+            if let Some(ref mut tile_list) = tile_list
+                && !is_empty
+            {
+                tile_list
+                    .write(&msg.0)
+                    .expect("Tile should be written to the tile list");
+            }
+
+            inserted += 1;
+
+            if committed_tx.is_some() {
+                batch.push(msg.0);
+            }
+
+            if inserted % batch_size == 0 {
+                conn.execute("COMMIT", ())
+                    .expect("Transaction should be committed");
+
+                if let Some(ref committed_tx) = committed_tx {
+                    committed_tx
+                        .send(mem::take(&mut batch))
+                        .expect("Committed tiles should be sent");
+                }
+            }
+
+            stats_tx
+                .send(StatsMsg::Duration(
+                    Metric::Insert,
+                    Instant::now().duration_since(instant),
+                ))
+                .expect("Insert duration stats should be sent");
+        };
+
+        for msg in data_rx {
+            if png_post_compress && compressor.is_none() {
+                samples.push(msg);
+
+                if samples.len() == DICTIONARY_SAMPLES {
+                    let dictionary = train_dictionary(&conn, &samples);
+
+                    compressor = Some(
+                        Compressor::with_dictionary(0, &dictionary)
+                            .expect("zstd compressor should be created"),
+                    );
+
+                    for msg in samples.drain(..) {
+                        insert(msg, compressor.as_mut());
+                    }
+                }
+            } else {
+                insert(msg, compressor.as_mut());
+            }
+        }
+
+        // less tiles than needed for training
+        if !samples.is_empty() {
+            let dictionary = train_dictionary(&conn, &samples);
+
+            let mut compressor = Compressor::with_dictionary(0, &dictionary)
+                .expect("zstd compressor should be created");
+
+            for msg in samples {
+                insert(msg, Some(&mut compressor));
+            }
+        }
+
+        // the last partial batch
+        if inserted % batch_size != 0 {
+            conn.execute("COMMIT", ())
+                .expect("Transaction should be committed");
+
+            if let Some(committed_tx) = committed_tx {
+                committed_tx
+                    .send(batch)
+                    .expect("Committed tiles should be sent");
+            }
+        }
+
+        // replaced tiles may leave the image data unreferenced
+        if dedup && matches!(insert_mode, InsertMode::ReplaceOnConflict) {
+            conn.execute(
+                "DELETE FROM image_data WHERE tile_id NOT IN (SELECT tile_id FROM map)",
+                (),
+            )
+            .expect("Unreferenced image data should be deleted");
+        }
+
+        // leave complete database file also if the process gets killed later
+        conn.query_row("PRAGMA wal_checkpoint(FULL)", (), |_| Ok(()))
+            .expect("WAL should be checkpointed");
+
+        if let Some(mut tile_list) = tile_list {
+            tile_list
+                .writer
+                .flush()
+                .expect("Tile list should be flushed");
+        }
+    });
+
+    Ok((insert_thread, data_tx))
+}
+
+/// Like `new` but writes the tiles as `{zoom}/{x}/{y}.{ext}` files into the `target_dir`, with
+/// world files if `world_file_tile_size` is set. Alpha of JPEG tiles is dropped.
+pub fn new_directory(
+    target_dir: &Path,
+    config: DirectoryConfig,
+    stats_tx: Sender<StatsMsg>,
+    mut tile_list: Option<TileListWriter>,
+) -> io::Result<Inserter> {
+    let DirectoryConfig {
+        num_threads,
+        ext,
+        insert_mode,
+        tile_row_order,
+        world_file_tile_size,
+    } = config;
+
+    let (data_tx, data_rx) = sync_channel::<TileMsg>(num_threads as usize * 16);
+
+    fs::create_dir_all(target_dir)?;
+
+    let target_dir = target_dir.to_path_buf();
+
+    let insert_thread = thread::spawn(move || {
+        for (tile, data, _) in data_rx {
+            // empty tiles are stored only for resuming which the directory doesn't support
+            if data.is_empty() {
+                continue;
+            }
+
+            let instant = Instant::now();
+
+            let path = target_dir.join(match tile_row_order {
+                TileRowOrder::Tms => tile.zoom_path_tms(ext),
+                TileRowOrder::Xyz => tile.zoom_path(ext),
+            });
+
+            if matches!(insert_mode, InsertMode::IgnoreOnConflict) && path.exists() {
+                continue;
+            }
+
+            // idempotent, so it doesn't matter if the directory already exists
+            fs::create_dir_all(path.parent().expect("tile path should have parent"))
+                .expect("Tile directory should be created");
+
+            fs::write(&path, data).expect("Tile should be written");
+
+            if let Some(tile_size) = world_file_tile_size {
+                write_world_file(&path, &tile, tile_size).expect("World file should be written");
+            }
+
+            if let Some(ref mut tile_list) = tile_list {
+                tile_list
+                    .write(&tile)
+                    .expect("Tile should be written to the tile list");
+            }
+
             stats_tx
                 .send(StatsMsg::Duration(
                     Metric::Insert,
@@ -64,6 +529,13 @@ pub fn new(
                 ))
                 .expect("Insert duration stats should be sent");
         }
+
+        if let Some(mut tile_list) = tile_list {
+            tile_list
+                .writer
+                .flush()
+                .expect("Tile list should be flushed");
+        }
     });
 
     Ok((insert_thread, data_tx))
@@ -0,0 +1,48 @@
+use std::path::{Path, PathBuf};
+use tilemath::Tile;
+
+/// Filesystem paths of tiles in the `{zoom}/{x}/{y}.{ext}` directory layout
+pub trait TilePath: Sized {
+    /// Returns XYZ path `{zoom}/{x}/{y}.{ext}`.
+    fn zoom_path(&self, ext: &str) -> PathBuf;
+
+    /// Returns TMS path `{zoom}/{x}/{reversed y}.{ext}`.
+    fn zoom_path_tms(&self, ext: &str) -> PathBuf;
+
+    /// Parses tile from XYZ path ending with `{zoom}/{x}/{y}.{ext}`.
+    fn from_path(path: &Path) -> Option<Self>;
+}
+
+impl TilePath for Tile {
+    fn zoom_path(&self, ext: &str) -> PathBuf {
+        [
+            self.zoom.to_string(),
+            self.x.to_string(),
+            format!("{}.{ext}", self.y),
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn zoom_path_tms(&self, ext: &str) -> PathBuf {
+        [
+            self.zoom.to_string(),
+            self.x.to_string(),
+            format!("{}.{ext}", self.reversed_y()),
+        ]
+        .iter()
+        .collect()
+    }
+
+    fn from_path(path: &Path) -> Option<Self> {
+        let y = path.file_stem()?.to_str()?.parse().ok()?;
+
+        let path = path.parent()?;
+
+        let x = path.file_name()?.to_str()?.parse().ok()?;
+
+        let zoom: u8 = path.parent()?.file_name()?.to_str()?.parse().ok()?;
+
+        (zoom < 32 && x < 1 << zoom && y < 1 << zoom).then_some(Self { zoom, x, y })
+    }
+}
@@ -0,0 +1,147 @@
+use tilemath::Tile;
+
+/// Traversal of the tile pyramid from a tile down to its descendants
+pub trait TileTree {
+    /// Returns iterator of the tile followed by all its descendants down to `max_zoom` in depth-first order.
+    fn depth_first_children(&self, max_zoom: u8) -> DepthFirstChildren;
+}
+
+impl TileTree for Tile {
+    fn depth_first_children(&self, max_zoom: u8) -> DepthFirstChildren {
+        DepthFirstChildren {
+            stack: if self.zoom <= max_zoom {
+                vec![*self]
+            } else {
+                vec![]
+            },
+            max_zoom,
+        }
+    }
+}
+
+/// Stack based depth-first iterator, see `TileTree::depth_first_children`
+pub struct DepthFirstChildren {
+    stack: Vec<Tile>,
+    max_zoom: u8,
+}
+
+impl Iterator for DepthFirstChildren {
+    type Item = Tile;
+
+    fn next(&mut self) -> Option<Tile> {
+        let tile = self.stack.pop()?;
+
+        if tile.zoom < self.max_zoom {
+            // reversed so that the first child is visited first
+            self.stack.extend(tile.children().into_iter().rev());
+        }
+
+        Some(tile)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const TILES: [Tile; 4] = [
+        Tile {
+            zoom: 0,
+            x: 0,
+            y: 0,
+        },
+        Tile {
+            zoom: 1,
+            x: 1,
+            y: 0,
+        },
+        Tile {
+            zoom: 3,
+            x: 5,
+            y: 2,
+        },
+        Tile {
+            zoom: 5,
+            x: 31,
+            y: 17,
+        },
+    ];
+
+    fn is_descendant_or_self(tile: &Tile, of: &Tile) -> bool {
+        tile.zoom >= of.zoom
+            && tile
+                .ancestor(tile.zoom - of.zoom)
+                .expect("should have ancestor")
+                == *of
+    }
+
+    #[test]
+    fn visits_each_descendant_once() {
+        for tile in TILES {
+            for depth in 0..=4 {
+                let visited: Vec<_> = tile.depth_first_children(tile.zoom + depth).collect();
+
+                let unique: HashSet<_> = visited.iter().copied().collect();
+
+                assert_eq!(unique.len(), visited.len(), "{tile} depth {depth}");
+
+                // 1 + 4 + 16 + ... tiles
+                assert_eq!(
+                    visited.len(),
+                    (0..=depth).map(|d| 1 << (2 * d)).sum::<usize>(),
+                    "{tile} depth {depth}"
+                );
+
+                assert!(
+                    visited
+                        .iter()
+                        .all(|child| is_descendant_or_self(child, &tile))
+                );
+
+                assert!(visited.iter().all(|child| child.zoom <= tile.zoom + depth));
+            }
+        }
+    }
+
+    #[test]
+    fn starts_with_tile_and_precedes_children_by_parents() {
+        for tile in TILES {
+            let visited: Vec<_> = tile.depth_first_children(tile.zoom + 3).collect();
+
+            assert_eq!(visited[0], tile);
+
+            for (i, child) in visited.iter().enumerate().skip(1) {
+                let parent = child.parent().expect("should have parent");
+
+                assert!(visited[..i].contains(&parent), "{child} before {parent}");
+            }
+        }
+    }
+
+    #[test]
+    fn visits_subtrees_contiguously() {
+        let tile = TILES[2];
+
+        let visited: Vec<_> = tile.depth_first_children(tile.zoom + 3).collect();
+
+        for child in tile.children() {
+            let positions: Vec<_> = visited
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| is_descendant_or_self(t, &child))
+                .map(|(i, _)| i)
+                .collect();
+
+            assert_eq!(
+                positions.last().unwrap() - positions[0] + 1,
+                positions.len()
+            );
+        }
+    }
+
+    #[test]
+    fn is_empty_below_max_zoom() {
+        assert_eq!(TILES[2].depth_first_children(2).count(), 0);
+    }
+}
@@ -0,0 +1,46 @@
+use crate::args::Args;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+
+/// Returns TileJSON 3.0.0 of the tiles at `tiles_url` with the name, zoom range and scheme of the
+/// arguments. `bounds` are WGS84 `[min_lon, min_lat, max_lon, max_lat]` with the zoom of the
+/// center. Entries of `metadata` are added as fields, overriding the generated ones.
+pub fn tilejson(
+    tiles_url: &str,
+    args: &Args,
+    bounds: Option<([f64; 4], u8)>,
+    metadata: &HashMap<String, String>,
+) -> Value {
+    let mut tilejson = json!({
+        "tilejson": "3.0.0",
+        "name": args.output_name,
+        "tiles": [tiles_url],
+        "scheme": args.tile_row_order.as_str(),
+        "minzoom": args.min_zoom,
+        "maxzoom": args.max_zoom,
+    });
+
+    if let Some(ref description) = args.output_description {
+        tilejson["description"] = json!(description);
+    }
+
+    if let Some(ref attribution) = args.output_attribution {
+        tilejson["attribution"] = json!(attribution);
+    }
+
+    if let Some((bounds, zoom)) = bounds {
+        tilejson["bounds"] = json!(bounds);
+
+        tilejson["center"] = json!([
+            (bounds[0] + bounds[2]) / 2.0,
+            (bounds[1] + bounds[3]) / 2.0,
+            zoom
+        ]);
+    }
+
+    for (key, value) in metadata {
+        tilejson[key] = json!(value);
+    }
+
+    tilejson
+}
@@ -1,14 +1,38 @@
+use crate::args::Format;
+use indicatif::{ProgressBar, ProgressStyle};
 use std::{
+    collections::{HashMap, VecDeque},
     fmt::{self, Display, Formatter},
+    mem,
     sync::mpsc::{self, Sender},
     thread::{self, JoinHandle},
     time::{Duration, Instant},
 };
 use tilemath::Tile;
+use tracing::{debug, info, warn};
 
 pub enum StatsMsg {
     Duration(Metric, Duration),
-    Stats(f32, usize, Tile),
+    /// Processed tiles, total tiles, buffer cache size and the current tile
+    Stats(usize, usize, usize, Tile),
+    /// Number of tiles completed since the previous message
+    TileComplete(usize),
+    ChecksumMismatch,
+    ZoomProgress(HashMap<u8, usize>),
+    /// Format chosen for a tile with `--format auto`
+    AutoFormat(Format),
+    /// PSNR of a tile quantized with `--format png8`, `None` if lossless
+    Quantization(Option<f64>),
+    /// Found and missing child tiles in the buffer cache when composing a parent tile
+    BufferCache {
+        hits: usize,
+        misses: usize,
+    },
+    /// Contended and all lock accesses of the shared structures
+    Contention {
+        buffer_cache: (usize, usize),
+        state: (usize, usize),
+    },
 }
 
 pub enum Metric {
@@ -19,6 +43,9 @@ pub enum Metric {
     Compose,
 }
 
+/// Period over which the throughput is measured
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(60);
+
 #[derive(Default)]
 struct TimeTrack {
     count: u32,
@@ -30,6 +57,11 @@ impl TimeTrack {
         self.duration += duration;
         self.count += 1;
     }
+
+    /// Returns average duration in milliseconds, `None` if nothing was tracked.
+    fn avg_ms(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.duration.as_secs_f64() * 1000.0 / f64::from(self.count))
+    }
 }
 
 impl Display for TimeTrack {
@@ -58,6 +90,11 @@ pub struct TimeStats {
     warp: TimeTrack,
     compose: TimeTrack,
     encode: TimeTrack,
+    cache_hits: usize,
+    cache_misses: usize,
+    completed: usize,
+    /// Tile completions within the throughput window
+    completions: VecDeque<(Instant, usize)>,
 }
 
 impl TimeStats {
@@ -70,38 +107,144 @@ impl TimeStats {
             Metric::Encode => self.encode.add(duration),
         }
     }
+
+    pub fn add_cache_access(&mut self, hits: usize, misses: usize) {
+        self.cache_hits += hits;
+        self.cache_misses += misses;
+    }
+
+    pub fn add_completed(&mut self, count: usize, now: Instant) {
+        self.completed += count;
+
+        self.completions.push_back((now, count));
+
+        self.expire_completions(now);
+    }
+
+    fn expire_completions(&mut self, now: Instant) {
+        while self
+            .completions
+            .front()
+            .is_some_and(|(instant, _)| now.duration_since(*instant) > THROUGHPUT_WINDOW)
+        {
+            self.completions.pop_front();
+        }
+    }
+
+    /// Returns tiles completed per second within the throughput window ending at `now`. The window
+    /// is shortened to start at `start` of the collection.
+    pub fn tiles_per_second(&mut self, now: Instant, start: Instant) -> f64 {
+        self.expire_completions(now);
+
+        let window = now.duration_since(start).min(THROUGHPUT_WINDOW);
+
+        if window.is_zero() {
+            return 0.0;
+        }
+
+        self.completions
+            .iter()
+            .map(|(_, count)| count)
+            .sum::<usize>() as f64
+            / window.as_secs_f64()
+    }
+
+    /// Returns estimated time to complete the remaining tiles of `total` as HH:MM:SS.
+    pub fn eta(&self, total: usize, tiles_per_second: f64) -> String {
+        if tiles_per_second <= 0.0 {
+            return "-".into();
+        }
+
+        let secs = (total.saturating_sub(self.completed) as f64 / tiles_per_second).round() as u64;
+
+        format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    }
+
+    /// Resets the durations and cache accesses of the stats period, keeping the tile completions.
+    pub fn reset(&mut self) {
+        *self = Self {
+            completed: self.completed,
+            completions: mem::take(&mut self.completions),
+            ..Self::default()
+        };
+    }
 }
 
-impl Display for TimeStats {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "select: {}, insert: {}, warp: {}, compose: {}, processing: {} | {}",
-            self.select,
-            self.insert,
-            self.warp,
-            self.compose,
-            self.encode,
-            (self.select.duration
-                + self.insert.duration
-                + self.warp.duration
-                + self.compose.duration)
-                .as_millis()
-        )
+/// Returns the stats as a JSON object for `--json-progress`.
+fn json_progress(
+    pct: f32,
+    queue_len: usize,
+    tile: &Tile,
+    stats: &TimeStats,
+    tiles_per_second: f64,
+    eta: &str,
+) -> serde_json::Value {
+    serde_json::json!({
+        "percent": pct,
+        "buffer_cache_len": queue_len,
+        "current_tile": tile.to_string(),
+        "select_ms_avg": stats.select.avg_ms(),
+        "insert_ms_avg": stats.insert.avg_ms(),
+        "warp_ms_avg": stats.warp.avg_ms(),
+        "compose_ms_avg": stats.compose.avg_ms(),
+        "encode_ms_avg": stats.encode.avg_ms(),
+        "tiles_per_second": tiles_per_second,
+        "eta": eta,
+    })
+}
+
+/// Returns contention rate in percent.
+fn contention_pct((contentions, accesses): (usize, usize)) -> f32 {
+    if accesses == 0 {
+        0.0
+    } else {
+        contentions as f32 / accesses as f32 * 100.0
     }
 }
 
-pub fn new(debug: bool) -> (Sender<StatsMsg>, JoinHandle<()>) {
+const PROGRESS_BAR_TEMPLATE: &str = "{wide_bar} {percent:>3} % | {per_sec} | ETA {eta} | {msg}";
+
+/// Starts the stats collector thread. With `progress_bar` the progress is shown as an in-place
+/// updated bar instead of printing the stats periodically. With `json` the periodic stats are
+/// printed as JSON objects.
+pub fn new(
+    debug: bool,
+    contention_threshold_pct: Option<f32>,
+    progress_bar: bool,
+    json: bool,
+) -> (Sender<StatsMsg>, JoinHandle<()>) {
     let (tx, rx) = mpsc::channel::<StatsMsg>();
 
     let mut stats = TimeStats::default();
 
-    let mut last_log = Instant::now();
+    let start = Instant::now();
+
+    let mut last_log = start;
 
     let mut pct = 0_f32;
 
+    let mut total = 0_usize;
+
     let mut queue_len = 0_usize;
 
+    let mut checksum_mismatches = 0_usize;
+
+    let mut zoom_progress = HashMap::new();
+
+    let mut jpeg_tiles = 0_usize;
+
+    let mut png_tiles = 0_usize;
+
+    let mut quantized_tiles = 0_usize;
+
+    let mut lossless_tiles = 0_usize;
+
+    let mut psnr_sum = 0_f64;
+
+    let mut contention = None;
+
+    let mut bar: Option<ProgressBar> = None;
+
     let mut tile = Tile {
         x: 0,
         y: 0,
@@ -114,26 +257,161 @@ pub fn new(debug: bool) -> (Sender<StatsMsg>, JoinHandle<()>) {
                 StatsMsg::Duration(typ, duration) => {
                     let now = Instant::now();
 
-                    if now.duration_since(last_log).as_secs() > 10 {
+                    if json && now.duration_since(last_log).as_secs() > 10 {
                         last_log = now;
 
+                        let tiles_per_second = stats.tiles_per_second(now, start);
+
                         println!(
-                            "{}{pct:.2} % | {queue_len} | {tile} | {stats}",
-                            if debug { "\n" } else { "" }
+                            "{}",
+                            json_progress(
+                                pct,
+                                queue_len,
+                                &tile,
+                                &stats,
+                                tiles_per_second,
+                                &stats.eta(total, tiles_per_second)
+                            )
                         );
 
-                        stats = TimeStats::default();
+                        stats.reset();
+                    } else if !progress_bar && now.duration_since(last_log).as_secs() > 10 {
+                        last_log = now;
+
+                        let tiles_per_second = stats.tiles_per_second(now, start);
+
+                        info!(
+                            percent = pct,
+                            tiles_per_second,
+                            eta = %stats.eta(total, tiles_per_second),
+                            buffer_cache_len = queue_len,
+                            current_tile = %tile,
+                            select = %stats.select,
+                            insert = %stats.insert,
+                            warp = %stats.warp,
+                            compose = %stats.compose,
+                            processing = %stats.encode,
+                            cache_hits = stats.cache_hits,
+                            cache_misses = stats.cache_misses,
+                            checksum_mismatches = (checksum_mismatches > 0).then_some(checksum_mismatches),
+                            jpeg_tiles = (jpeg_tiles + png_tiles > 0).then_some(jpeg_tiles),
+                            png_tiles = (jpeg_tiles + png_tiles > 0).then_some(png_tiles),
+                            avg_psnr_db =
+                                (quantized_tiles > 0).then(|| psnr_sum / quantized_tiles as f64),
+                            "Progress"
+                        );
+
+                        if let Some((buffer_cache, state)) = contention {
+                            let buffer_cache = contention_pct(buffer_cache);
+
+                            let state = contention_pct(state);
+
+                            if debug {
+                                debug!(
+                                    buffer_cache_pct = buffer_cache,
+                                    state_pct = state,
+                                    "Lock contention"
+                                );
+                            }
+
+                            if let Some(threshold) = contention_threshold_pct
+                                && buffer_cache.max(state) > threshold
+                            {
+                                warn!(
+                                    "Lock contention exceeds {threshold} %, consider reducing --num-threads or increasing --warp-zoom-offset."
+                                );
+                            }
+                        }
+
+                        if debug && !zoom_progress.is_empty() {
+                            let mut zooms: Vec<_> = zoom_progress.iter().collect();
+
+                            zooms.sort();
+
+                            debug!(
+                                "pending by zoom: {}",
+                                zooms
+                                    .iter()
+                                    .map(|(zoom, count)| format!("{zoom}: {count}"))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            );
+                        }
+
+                        stats.reset();
                     }
 
                     stats.add(&typ, duration);
                 }
-                StatsMsg::Stats(pct_, queue_len_, tile_) => {
-                    pct = pct_;
+                StatsMsg::Stats(processed, total_, queue_len_, tile_) => {
+                    total = total_;
+                    pct = processed as f32 / total as f32 * 100.0;
                     queue_len = queue_len_;
                     tile = tile_;
+
+                    if progress_bar {
+                        let bar = bar.get_or_insert_with(|| {
+                            ProgressBar::new(total as u64).with_style(
+                                ProgressStyle::with_template(PROGRESS_BAR_TEMPLATE)
+                                    .expect("progress bar template should be valid"),
+                            )
+                        });
+
+                        bar.set_position(processed as u64);
+
+                        bar.set_message(format!("{queue_len} | {tile}"));
+                    }
+                }
+                StatsMsg::TileComplete(count) => {
+                    stats.add_completed(count, Instant::now());
+                }
+                StatsMsg::BufferCache { hits, misses } => {
+                    stats.add_cache_access(hits, misses);
+                }
+                StatsMsg::ChecksumMismatch => {
+                    checksum_mismatches += 1;
+                }
+                StatsMsg::ZoomProgress(zoom_progress_) => {
+                    zoom_progress = zoom_progress_;
+                }
+                StatsMsg::AutoFormat(Format::JPEG) => {
+                    jpeg_tiles += 1;
+                }
+                StatsMsg::AutoFormat(_) => {
+                    png_tiles += 1;
+                }
+                StatsMsg::Quantization(Some(psnr)) => {
+                    quantized_tiles += 1;
+
+                    psnr_sum += psnr;
+                }
+                StatsMsg::Quantization(None) => {
+                    lossless_tiles += 1;
+                }
+                StatsMsg::Contention {
+                    buffer_cache,
+                    state,
+                } => {
+                    contention = Some((buffer_cache, state));
                 }
             }
         }
+
+        if let Some(bar) = bar {
+            bar.finish();
+        }
+
+        if !json && quantized_tiles + lossless_tiles > 0 {
+            info!(
+                "Quantized tiles: {}, lossless: {lossless_tiles}, average PSNR of lossy: {}",
+                quantized_tiles + lossless_tiles,
+                if quantized_tiles > 0 {
+                    format!("{:.2} dB", psnr_sum / quantized_tiles as f64)
+                } else {
+                    "-".to_string()
+                }
+            );
+        }
     });
 
     (tx, thread)
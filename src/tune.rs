@@ -0,0 +1,184 @@
+use crate::{
+    args::Format,
+    avif_tile,
+    bbox::TileRange,
+    quantize,
+    warp::{self, WarpOptions},
+    webp_tile,
+};
+use gdal::Dataset;
+use image::{ImageEncoder, codecs::png::PngEncoder};
+use std::time::Instant;
+use tilemath::Tile;
+use tracing::{info, warn};
+
+/// Number of megatiles to warp for every tested zoom offset
+const SAMPLE_SIZE: usize = 5;
+
+/// Number of tiles to encode for estimating the average encoded tile size
+const SIZE_SAMPLE_SIZE: usize = 100;
+
+/// Warps few megatiles for every zoom offset from 1 to 5 and returns the one with the highest throughput.
+pub fn tune_warp_zoom_offset(
+    source_ds: &Dataset,
+    tiles: &[Tile],
+    tile_size: u16,
+    options: &WarpOptions,
+) -> Option<u8> {
+    let band_count = source_ds.raster_count().div_ceil(2) * 2;
+
+    let mut best: Option<(u8, f64)> = None;
+
+    for zoom_offset in 1..=5_u8 {
+        let Ok(mega_size) = u16::try_from(u32::from(tile_size) << zoom_offset) else {
+            break;
+        };
+
+        let mut megatiles = Vec::with_capacity(SAMPLE_SIZE);
+
+        // tiles are processed from the end
+        for tile in tiles.iter().rev() {
+            let Some(ancestor) = tile.ancestor(zoom_offset) else {
+                break;
+            };
+
+            if !megatiles.contains(&ancestor) {
+                megatiles.push(ancestor);
+
+                if megatiles.len() == SAMPLE_SIZE {
+                    break;
+                }
+            }
+        }
+
+        if megatiles.is_empty() {
+            continue;
+        }
+
+        let instant = Instant::now();
+
+        for megatile in &megatiles {
+            if let Err(code) =
+                warp::warp_tile(source_ds, megatile, mega_size, 0, band_count, options)
+            {
+                warn!("Warping of {megatile} failed with error code: {code}");
+
+                return None;
+            }
+        }
+
+        let tiles_per_sec =
+            (megatiles.len() << (2 * zoom_offset)) as f64 / instant.elapsed().as_secs_f64();
+
+        info!("Warp zoom offset {zoom_offset}: {tiles_per_sec:.1} tiles/s");
+
+        if best.is_none_or(|(_, best_tiles_per_sec)| tiles_per_sec > best_tiles_per_sec) {
+            best = Some((zoom_offset, tiles_per_sec));
+        }
+    }
+
+    best.map(|(zoom_offset, _)| zoom_offset)
+}
+
+/// Warps and encodes up to `SIZE_SAMPLE_SIZE` tiles spread evenly over the range. Returns number of
+/// sampled non-empty tiles and their average encoded size in bytes. On failure returns GDAL error code.
+pub fn sample_tile_size(
+    source_ds: &Dataset,
+    range: &TileRange,
+    tile_size: u16,
+    options: &WarpOptions,
+    format: Format,
+    quality: u8,
+    avif_speed: u8,
+) -> Result<Option<(usize, f64)>, i32> {
+    let band_count = source_ds.raster_count().div_ceil(2) * 2;
+
+    let mut sampled = 0;
+
+    let mut total_size = 0;
+
+    for tile in range.sample(SIZE_SAMPLE_SIZE, 0) {
+        let target_ds = warp::warp_tile(source_ds, &tile, tile_size, 0, band_count, options)?;
+
+        let size = tile_size as usize;
+
+        let buffers: Vec<_> = target_ds
+            .rasterbands()
+            .map(|band| {
+                band.expect("raster band should be obtained")
+                    .read_as::<u8>((0, 0), (size, size), (size, size), None)
+                    .expect("band should be read")
+                    .into_shape_and_vec()
+                    .1
+            })
+            .collect();
+
+        if buffers[band_count - 1].iter().all(|&alpha| alpha == 0) {
+            continue;
+        }
+
+        let mut encoded = Vec::new();
+
+        if matches!(format, Format::JPEG) {
+            let rgb: Vec<_> = (0..size * size)
+                .flat_map(|i| {
+                    buffers[0..band_count - 1]
+                        .iter()
+                        .map(move |buffer| buffer[i])
+                })
+                .collect();
+
+            jpeg_encoder::Encoder::new(&mut encoded, quality)
+                .encode(
+                    &rgb,
+                    tile_size,
+                    tile_size,
+                    if band_count == 2 {
+                        jpeg_encoder::ColorType::Luma
+                    } else {
+                        jpeg_encoder::ColorType::Rgb
+                    },
+                )
+                .expect("JPEG should be encoded");
+        } else {
+            let rgba: Vec<_> = (0..size * size)
+                .flat_map(|i| buffers.iter().map(move |buffer| buffer[i]))
+                .collect();
+
+            if matches!(format, Format::AVIF) {
+                encoded =
+                    avif_tile::encode(&rgba, band_count, tile_size, quality, avif_speed, true)
+                        .expect("AVIF should be encoded");
+            } else if matches!(format, Format::WebP) {
+                encoded = webp_tile::encode(&rgba, band_count, tile_size, quality, true);
+            } else if matches!(format, Format::PNG8) {
+                encoded = quantize::encode_png8(&rgba, band_count, tile_size, true)
+                    .expect("PNG should be encoded")
+                    .png;
+            } else {
+                PngEncoder::new_with_quality(
+                    &mut encoded,
+                    image::codecs::png::CompressionType::Best,
+                    image::codecs::png::FilterType::Adaptive,
+                )
+                .write_image(
+                    &rgba,
+                    u32::from(tile_size),
+                    u32::from(tile_size),
+                    if band_count == 2 {
+                        image::ExtendedColorType::La8
+                    } else {
+                        image::ExtendedColorType::Rgba8
+                    },
+                )
+                .expect("PNG should be encoded");
+            }
+        }
+
+        sampled += 1;
+
+        total_size += encoded.len();
+    }
+
+    Ok((sampled > 0).then(|| (sampled, total_size as f64 / sampled as f64)))
+}
@@ -0,0 +1,119 @@
+use crate::source_list::open_source;
+use gdal::raster::GdalDataType;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use std::path::Path;
+
+/// Reads every block of the band and returns descriptions of the blocks failing to read.
+fn validate_band_blocks(path: &Path, overview_level: u8, index: usize) -> Vec<String> {
+    let source_ds = match open_source(path, overview_level, None) {
+        Ok(source_ds) => source_ds,
+        Err(e) => return vec![format!("Band {index}: error opening source: {e}")],
+    };
+
+    let band = match source_ds.rasterband(index) {
+        Ok(band) => band,
+        Err(e) => return vec![format!("Band {index}: error obtaining band: {e}")],
+    };
+
+    let (width, height) = band.size();
+
+    let (block_width, block_height) = band.block_size();
+
+    let mut issues = Vec::new();
+
+    for y in (0..height).step_by(block_height) {
+        for x in (0..width).step_by(block_width) {
+            let size = (block_width.min(width - x), block_height.min(height - y));
+
+            if let Err(e) = band.read_as::<u8>((x as isize, y as isize), size, size, None) {
+                issues.push(format!("Band {index}: error reading block at {x},{y}: {e}"));
+            }
+        }
+    }
+
+    issues
+}
+
+/// Validates the source raster before tiling and returns descriptions of all the found issues.
+///
+/// Checks band count, data types, nodata values and geo-transform, then reads every block of all
+/// the bands in parallel.
+pub fn validate_input(path: &Path, overview_level: u8) -> Vec<String> {
+    let source_ds = match open_source(path, overview_level, None) {
+        Ok(source_ds) => source_ds,
+        Err(e) => return vec![format!("Error opening source: {e}")],
+    };
+
+    let mut issues = Vec::new();
+
+    let band_count = source_ds.raster_count();
+
+    if !(1..=4).contains(&band_count) {
+        issues.push(format!(
+            "Unsupported raster with {band_count} band(s), supports only G, GA, RGB, RGBA rasters"
+        ));
+    }
+
+    let mut no_data_values = Vec::with_capacity(band_count);
+
+    for index in 1..=band_count {
+        match source_ds.rasterband(index) {
+            Ok(band) => {
+                let band_type = band.band_type();
+
+                if band_type != GdalDataType::UInt8 {
+                    issues.push(format!(
+                        "Band {index} has data type {band_type}, only 8 bit unsigned integer is supported"
+                    ));
+                }
+
+                no_data_values.push(band.no_data_value());
+            }
+            Err(e) => issues.push(format!("Band {index}: error obtaining band: {e}")),
+        }
+    }
+
+    if no_data_values
+        .windows(2)
+        .any(|pair| pair[0].map(f64::to_bits) != pair[1].map(f64::to_bits))
+    {
+        issues.push(format!(
+            "Inconsistent nodata values of the bands: {}",
+            no_data_values
+                .iter()
+                .map(|no_data| no_data.map_or_else(|| "none".to_string(), |v| v.to_string()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    match source_ds.geo_transform() {
+        Ok(geo_transform) => {
+            if geo_transform[1] <= 0.0 {
+                issues.push(format!(
+                    "Invalid geo-transform pixel width {}, must be positive",
+                    geo_transform[1]
+                ));
+            }
+
+            if geo_transform[5] >= 0.0 {
+                issues.push(format!(
+                    "Invalid geo-transform pixel height {}, must be negative",
+                    geo_transform[5]
+                ));
+            }
+        }
+        Err(e) => issues.push(format!("Missing geo-transform: {e}")),
+    }
+
+    drop(source_ds);
+
+    issues.extend(
+        (1..=band_count)
+            .into_par_iter()
+            .flat_map_iter(|index| validate_band_blocks(path, overview_level, index))
+            .collect::<Vec<_>>(),
+    );
+
+    issues
+}
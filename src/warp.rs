@@ -1,99 +1,324 @@
-use gdal::Dataset;
+use crate::args::ResamplingAlgorithm;
+use gdal::{
+    Dataset, DriverManager,
+    raster::{ColorInterpretation, GdalType},
+};
 use gdal_sys::{
-    CPLErr, GDALChunkAndWarpImage, GDALCreateGenImgProjTransformer2, GDALCreateWarpOperation,
-    GDALCreateWarpOptions, GDALDestroyGenImgProjTransformer, GDALDestroyWarpOperation,
-    GDALDestroyWarpOptions, GDALGenImgProjTransform, GDALReprojectImage, GDALResampleAlg,
-    GDALWarpInitDefaultBandMapping,
+    CPLErr, CSLAddString, GDALApproxTransform, GDALApproxTransformerOwnsSubtransformer,
+    GDALChunkAndWarpImage, GDALCreateApproxTransformer, GDALCreateGenImgProjTransformer2,
+    GDALCreateWarpOperation, GDALCreateWarpOptions, GDALDestroyApproxTransformer,
+    GDALDestroyGenImgProjTransformer, GDALDestroyWarpOperation, GDALDestroyWarpOptions,
+    GDALGenImgProjTransform, GDALReprojectImage, GDALWarpInitDefaultBandMapping,
 };
 use std::{ffi::CString, ptr};
+use tilemath::Tile;
 
 pub enum Transform {
     Pipeline(String),
     Srs(String, String),
 }
 
-pub fn warp(source_ds: &Dataset, target_ds: &Dataset, tile_size: u16, transform: &Transform) {
+/// GDAL API used for warping
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WarpMethod {
+    /// `GDALChunkAndWarpImage` for the transformation pipeline, `GDALReprojectImage` otherwise
+    #[default]
+    Auto,
+    /// Always `GDALReprojectImage`
+    ReprojectImage,
+    /// Always `GDALChunkAndWarpImage`
+    ChunkAndWarp,
+}
+
+/// Options of warping the source
+#[derive(Clone, Copy)]
+pub struct WarpOptions<'a> {
+    pub transform: &'a Transform,
+    /// Compute the alpha from the source mask band, see `warp`
+    pub use_mask_band: bool,
+    pub method: WarpMethod,
+    /// Maximal error in pixels of the approximate transformer, like `gdalwarp -et`; 0 uses the
+    /// exact transformer
+    pub max_error: f64,
+    pub resampling: ResamplingAlgorithm,
+    /// Memory the warper may use for a chunk, like `gdalwarp -wm`; larger limit means less chunks
+    pub memory_limit_mb: f64,
+    /// WKT (multi)polygon in source pixel coordinates, pixels outside of it are not written
+    pub cutline: Option<&'a str>,
+}
+
+/// Warps source dataset into target dataset. On failure returns GDAL error code.
+///
+/// If `use_mask_band` is set, the last target band is used as alpha computed by the warper
+/// from the source mask band instead of being warped from the source. Otherwise with
+/// `GDALChunkAndWarpImage` the warper writes the last target band as alpha from the source alpha
+/// band (keeping partial transparency) or from the source coverage.
+///
+/// `GDALReprojectImage` creates its own transformer from the source and target SRS, so it can't be
+/// used with the transformation pipeline.
+pub fn warp(
+    source_ds: &Dataset,
+    target_ds: &Dataset,
+    tile_size: u16,
+    options: &WarpOptions,
+) -> Result<(), i32> {
+    let WarpOptions {
+        transform,
+        use_mask_band,
+        method,
+        max_error,
+        resampling,
+        memory_limit_mb,
+        cutline,
+    } = *options;
+
+    let chunk_and_warp = match method {
+        WarpMethod::Auto => matches!(transform, Transform::Pipeline(_)),
+        WarpMethod::ReprojectImage => false,
+        WarpMethod::ChunkAndWarp => true,
+    };
+
     unsafe {
         let warp_options = GDALCreateWarpOptions();
 
-        (*warp_options).eResampleAlg = GDALResampleAlg::GRA_Lanczos;
+        (*warp_options).eResampleAlg = resampling.gdal();
 
-        let result = match transform {
-            Transform::Pipeline(pipeline) => {
-                let mut options: Vec<*mut i8> = vec![];
+        (*warp_options).dfWarpMemoryLimit = memory_limit_mb * f64::from(1 << 20);
 
-                options.push(
-                    CString::new(format!("COORDINATE_OPERATION={pipeline}"))
-                        .unwrap()
-                        .into_raw(),
-                );
+        if let Some(cutline) = cutline {
+            let option =
+                CString::new(format!("CUTLINE={cutline}")).expect("cutline should not contain NUL");
 
-                options.push(ptr::null_mut());
+            (*warp_options).papszWarpOptions =
+                CSLAddString((*warp_options).papszWarpOptions, option.as_ptr());
+        }
 
-                let gen_img_proj_transformer = GDALCreateGenImgProjTransformer2(
-                    source_ds.c_dataset(),
-                    target_ds.c_dataset(),
-                    options.as_mut_ptr(),
-                );
+        if use_mask_band {
+            let band_count = target_ds.raster_count() as i32;
+
+            GDALWarpInitDefaultBandMapping(warp_options, band_count - 1);
 
-                drop(CString::from_raw(*options.first().unwrap()));
+            (*warp_options).nDstAlphaBand = band_count;
+        }
+
+        let result = if chunk_and_warp {
+            let options: Vec<_> = match transform {
+                Transform::Pipeline(pipeline) => vec![format!("COORDINATE_OPERATION={pipeline}")],
+                Transform::Srs(source_wkt, target_wkt) => {
+                    vec![
+                        format!("SRC_SRS={source_wkt}"),
+                        format!("DST_SRS={target_wkt}"),
+                    ]
+                }
+            }
+            .into_iter()
+            .map(|option| CString::new(option).expect("option should not contain NUL"))
+            .collect();
 
-                assert!(
-                    !gen_img_proj_transformer.is_null(),
-                    "Failed to create image projection transformer"
+            let mut options_ptrs: Vec<*mut i8> = options
+                .iter()
+                .map(|option| option.as_ptr().cast_mut())
+                .collect();
+
+            options_ptrs.push(ptr::null_mut());
+
+            let gen_img_proj_transformer = GDALCreateGenImgProjTransformer2(
+                source_ds.c_dataset(),
+                target_ds.c_dataset(),
+                options_ptrs.as_mut_ptr(),
+            );
+
+            assert!(
+                !gen_img_proj_transformer.is_null(),
+                "Failed to create image projection transformer"
+            );
+
+            if max_error > 0.0 {
+                let approx_transformer = GDALCreateApproxTransformer(
+                    Some(GDALGenImgProjTransform),
+                    gen_img_proj_transformer,
+                    max_error,
                 );
 
+                // destroying the approximate transformer destroys also the wrapped one
+                GDALApproxTransformerOwnsSubtransformer(approx_transformer, 1);
+
+                (*warp_options).pTransformerArg = approx_transformer;
+
+                (*warp_options).pfnTransformer = Some(GDALApproxTransform);
+            } else {
                 (*warp_options).pTransformerArg = gen_img_proj_transformer;
 
                 (*warp_options).pfnTransformer = Some(GDALGenImgProjTransform);
+            }
 
-                (*warp_options).hSrcDS = source_ds.c_dataset();
+            (*warp_options).hSrcDS = source_ds.c_dataset();
 
-                (*warp_options).hDstDS = target_ds.c_dataset();
+            (*warp_options).hDstDS = target_ds.c_dataset();
 
-                (*warp_options).nDstAlphaBand = 0;
+            if !use_mask_band {
+                let source_band_count = source_ds.raster_count() as i32;
 
-                (*warp_options).nSrcAlphaBand = 0;
+                let target_band_count = target_ds.raster_count() as i32;
 
-                GDALWarpInitDefaultBandMapping(warp_options, source_ds.raster_count() as i32);
+                // target has an extra alpha band if the source has none
+                if source_band_count == target_band_count {
+                    // propagate partial transparency of the source
+                    (*warp_options).nSrcAlphaBand = source_band_count;
 
-                let warp_operation = GDALCreateWarpOperation(warp_options);
+                    GDALWarpInitDefaultBandMapping(warp_options, source_band_count - 1);
+                } else {
+                    GDALWarpInitDefaultBandMapping(warp_options, source_band_count);
+                }
 
-                assert!(
-                    !warp_operation.is_null(),
-                    "Failed to create GDALCreateWarpOperation"
-                );
+                (*warp_options).nDstAlphaBand = target_band_count;
+            }
 
-                let result =
-                    GDALChunkAndWarpImage(warp_operation, 0, 0, tile_size.into(), tile_size.into());
+            let warp_operation = GDALCreateWarpOperation(warp_options);
 
-                if !(*warp_options).pTransformerArg.is_null() {
-                    GDALDestroyGenImgProjTransformer((*warp_options).pTransformerArg);
-                }
+            assert!(
+                !warp_operation.is_null(),
+                "Failed to create GDALCreateWarpOperation"
+            );
 
-                GDALDestroyWarpOperation(warp_operation);
+            let result =
+                GDALChunkAndWarpImage(warp_operation, 0, 0, tile_size.into(), tile_size.into());
 
-                result
+            if max_error > 0.0 {
+                GDALDestroyApproxTransformer((*warp_options).pTransformerArg);
+            } else {
+                GDALDestroyGenImgProjTransformer((*warp_options).pTransformerArg);
             }
-            Transform::Srs(source_wkt, target_wkt) => GDALReprojectImage(
+
+            GDALDestroyWarpOperation(warp_operation);
+
+            result
+        } else {
+            let (source_wkt, target_wkt) = match transform {
+                Transform::Srs(source_wkt, target_wkt) => (source_wkt.clone(), target_wkt.clone()),
+                Transform::Pipeline(_) => {
+                    unreachable!("--force-reproject-image conflicts with --transform-pipeline")
+                }
+            };
+
+            let source_wkt = CString::new(source_wkt).expect("WKT should not contain NUL");
+
+            let target_wkt = CString::new(target_wkt).expect("WKT should not contain NUL");
+
+            GDALReprojectImage(
                 source_ds.c_dataset(),
-                source_wkt.as_ptr().cast::<i8>(),
+                source_wkt.as_ptr(),
                 target_ds.c_dataset(),
-                target_wkt.as_ptr().cast::<i8>(),
-                GDALResampleAlg::GRA_Lanczos,
-                0.0,
-                0.0,
+                target_wkt.as_ptr(),
+                resampling.gdal(),
+                (*warp_options).dfWarpMemoryLimit,
+                max_error,
                 None,
                 ptr::null_mut(),
                 warp_options,
-            ),
+            )
         };
 
         GDALDestroyWarpOptions(warp_options);
 
-        assert!(
-            result == CPLErr::CE_None,
-            "ChunkAndWarpImage failed with error code: {result:?}"
-        );
+        if result == CPLErr::CE_None {
+            Ok(())
+        } else {
+            Err(result as i32)
+        }
     }
 }
+
+/// Creates in-memory dataset of the tile with bands of type `T` (gray or RGB with alpha).
+fn create_tile_dataset<T: GdalType>(
+    tile: &Tile,
+    size: u16,
+    padding: u16,
+    band_count: usize,
+) -> Dataset {
+    let bbox = tile.bounds(size);
+
+    let pixel_size = (bbox.max_x - bbox.min_x) / f64::from(size);
+
+    let padded_size = (size + 2 * padding) as usize;
+
+    let mut target_ds = DriverManager::get_driver_by_name("MEM")
+        .expect("MEM driver should be obtained")
+        .create_with_band_type::<T, _>("", padded_size, padded_size, band_count)
+        .expect("target dataset should be created");
+
+    let colors = if band_count == 2 {
+        vec![
+            ColorInterpretation::GrayIndex,
+            ColorInterpretation::AlphaBand,
+        ]
+    } else {
+        vec![
+            ColorInterpretation::RedBand,
+            ColorInterpretation::GreenBand,
+            ColorInterpretation::BlueBand,
+            ColorInterpretation::AlphaBand,
+        ]
+    };
+
+    for (i, color) in colors.into_iter().enumerate() {
+        target_ds
+            .rasterband(i + 1)
+            .unwrap()
+            .set_color_interpretation(color)
+            .unwrap();
+    }
+
+    target_ds
+        .set_geo_transform(&[
+            bbox.min_x - f64::from(padding) * pixel_size, // Top-left x
+            pixel_size,                                   // Pixel width
+            0.0,                                          // Rotation (x-axis)
+            bbox.max_y + f64::from(padding) * pixel_size, // Top-left y
+            0.0,                                          // Rotation (y-axis)
+            -pixel_size,                                  // Pixel height (negative for top-down)
+        ])
+        .expect("error setting geo transform");
+
+    target_ds
+}
+
+/// Creates in-memory dataset of the tile of specified size in pixels, extended by `padding` pixels
+/// on every side, and warps the source into it. On failure returns GDAL error code.
+pub fn warp_tile(
+    source_ds: &Dataset,
+    tile: &Tile,
+    size: u16,
+    padding: u16,
+    band_count: usize,
+    options: &WarpOptions,
+) -> Result<Dataset, i32> {
+    let target_ds = create_tile_dataset::<u8>(tile, size, padding, band_count);
+
+    warp(source_ds, &target_ds, size + 2 * padding, options)?;
+
+    Ok(target_ds)
+}
+
+/// Like `warp_tile` but warps single band elevation source into `Float32` elevation and alpha bands.
+pub fn warp_elevation_tile(
+    source_ds: &Dataset,
+    tile: &Tile,
+    size: u16,
+    padding: u16,
+    options: &WarpOptions,
+) -> Result<Dataset, i32> {
+    let target_ds = create_tile_dataset::<f32>(tile, size, padding, 2);
+
+    warp(
+        source_ds,
+        &target_ds,
+        size + 2 * padding,
+        &WarpOptions {
+            use_mask_band: false,
+            ..*options
+        },
+    )?;
+
+    Ok(target_ds)
+}
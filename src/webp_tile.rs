@@ -0,0 +1,49 @@
+/// Encodes the interleaved G+A or RGB+A pixels as lossy WebP. Without `with_alpha` the alpha is
+/// dropped, otherwise it is stored losslessly.
+pub fn encode(data: &[u8], band_count: usize, size: u16, quality: u8, with_alpha: bool) -> Vec<u8> {
+    let pixels: Vec<u8> = data
+        .chunks_exact(band_count)
+        .flat_map(|chunk| {
+            let rgb = if band_count == 2 {
+                [chunk[0], chunk[0], chunk[0]]
+            } else {
+                [chunk[0], chunk[1], chunk[2]]
+            };
+
+            rgb.into_iter()
+                .chain(with_alpha.then_some(chunk[band_count - 1]))
+        })
+        .collect();
+
+    let size = u32::from(size);
+
+    let encoder = if with_alpha {
+        webp::Encoder::from_rgba(&pixels, size, size)
+    } else {
+        webp::Encoder::from_rgb(&pixels, size, size)
+    };
+
+    encoder.encode(f32::from(quality)).to_vec()
+}
+
+/// Decodes WebP into interleaved pixels of `band_count` bands (G+A or RGB+A). Returns `None` on
+/// invalid data.
+pub fn decode(data: &[u8], band_count: usize) -> Option<Vec<u8>> {
+    let image = webp::Decoder::new(data).decode()?;
+
+    let channels = if image.is_alpha() { 4 } else { 3 };
+
+    let mut pixels = Vec::with_capacity(image.len() / channels * band_count);
+
+    for pixel in image.chunks_exact(channels) {
+        if band_count == 2 {
+            pixels.push(pixel[0]);
+        } else {
+            pixels.extend_from_slice(&pixel[0..3]);
+        }
+
+        pixels.push(if channels == 4 { pixel[3] } else { 255 });
+    }
+
+    Some(pixels)
+}
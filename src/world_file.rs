@@ -0,0 +1,43 @@
+use std::{fs, io, path::Path};
+use tilemath::Tile;
+
+/// Returns world file extension for the image extension, eg. `jgw` for `jpg` or `pgw` for `png`.
+fn world_file_ext(ext: &str) -> String {
+    let mut chars = ext.chars();
+
+    match (chars.next(), chars.last()) {
+        (Some(first), Some(last)) => format!("{first}{last}w"),
+        _ => format!("{ext}w"),
+    }
+}
+
+/// Returns content of the world file georeferencing the tile image in EPSG:3857.
+///
+/// Coordinates of the top-left pixel refer to its center.
+fn world_file(tile: &Tile, tile_size: u16) -> String {
+    let bbox = tile.bounds(tile_size);
+
+    let pixel_width = (bbox.max_x - bbox.min_x) / f64::from(tile_size);
+
+    let pixel_height = (bbox.max_y - bbox.min_y) / f64::from(tile_size);
+
+    format!(
+        "{pixel_width}\n0\n0\n{}\n{}\n{}\n",
+        -pixel_height,
+        bbox.min_x + pixel_width / 2.0,
+        bbox.max_y - pixel_height / 2.0
+    )
+}
+
+/// Writes the world file next to the tile image at `image_path`.
+pub fn write_world_file(image_path: &Path, tile: &Tile, tile_size: u16) -> io::Result<()> {
+    let ext = image_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default();
+
+    fs::write(
+        image_path.with_extension(world_file_ext(ext)),
+        world_file(tile, tile_size),
+    )
+}
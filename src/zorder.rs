@@ -0,0 +1,52 @@
+use tilemath::Tile;
+
+fn interleave(v: u32) -> u64 {
+    let mut result = 0u64;
+
+    for i in 0..32 {
+        result |= ((u64::from(v) >> i) & 1) << (i << 1);
+    }
+
+    result
+}
+
+/// Same as `interleave` but only for the lower 20 bits, enough for zoom levels up to 20.
+fn interleave_20(v: u32) -> u64 {
+    let mut result = 0u64;
+
+    for i in 0..20 {
+        result |= ((u64::from(v) >> i) & 1) << (i << 1);
+    }
+
+    result
+}
+
+// NOTE PDEP is microcoded (slow) on AMD CPUs before Zen 3
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "bmi2")]
+fn morton_code_pdep(tile: &Tile) -> u64 {
+    use std::arch::x86_64::_pdep_u64;
+
+    _pdep_u64(u64::from(tile.x), 0x5555_5555_5555_5555)
+        | _pdep_u64(u64::from(tile.y), 0xAAAA_AAAA_AAAA_AAAA)
+}
+
+/// Returns tile's [Morton code](https://en.wikipedia.org/wiki/Z-order_curve).
+pub fn morton_code(tile: &Tile) -> u64 {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("bmi2") {
+        // SAFETY: availability of BMI2 is checked above
+        return unsafe { morton_code_pdep(tile) };
+    }
+
+    if tile.zoom <= 20 {
+        interleave_20(tile.x) | (interleave_20(tile.y) << 1)
+    } else {
+        interleave(tile.x) | (interleave(tile.y) << 1)
+    }
+}
+
+/// Sort tiles according to morton code. Like `Tile::sort_by_zorder`, it does not take the zoom into the account.
+pub fn sort_by_zorder(tiles: &mut [Tile]) {
+    tiles.sort_by_cached_key(morton_code);
+}